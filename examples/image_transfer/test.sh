@@ -17,7 +17,7 @@ main() {
 
   podman build --build-arg VERSION=1 --tag "${image}" .
   kerek --host "${box}" transfer-images -- "${image}"
-  [[ "$(podman --url "${box}" run --rm "${image}" | tee /dev/stderr)" == 'v0' ]]
+  [[ "$(podman --url "${box}" run --rm "${image}" | tee /dev/stderr)" == 'v1' ]]
   kerek --host "${box}" transfer-images --force -- "${image}"
   [[ "$(podman --url "${box}" run --rm "${image}" | tee /dev/stderr)" == 'v1' ]]
 
@@ -13,8 +13,6 @@ test_container_engine() {
     vagrant snapshot push
   fi
 
-  echo 'Building buildable container images.'
-  docker compose build
   echo 'Pulling non-buildable container images.'
   docker compose pull --ignore-buildable
 
@@ -23,11 +21,8 @@ test_container_engine() {
     kerek --container-engine podman tunnel-ssh --ssh-config ssh_config staging
     trap 'fuser --kill -TERM kerek.sock' EXIT
 
-    docker compose config --images \
-      | kerek --host "unix://${PWD}/kerek.sock" transfer-images -
-
     kerek --host "unix://${PWD}/kerek.sock" \
-      deploy --no-build --pull never --remove-orphans --wait
+      deploy --build --transfer-images --remove-orphans --wait
   )
 
   echo 'Smoke testing.'
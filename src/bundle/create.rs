@@ -0,0 +1,116 @@
+use super::Image;
+use super::Manifest;
+use super::Writer;
+use crate::command;
+use crate::docker;
+use crate::docker_compose;
+use crate::log;
+use crate::tar;
+use crate::temp;
+use anyhow::Context;
+use std::collections;
+use std::fs;
+use std::path;
+
+pub fn go(
+    In {
+        docker_cli,
+        docker_compose_cli,
+        dry_run,
+        out,
+        service_names,
+    }: In,
+) -> anyhow::Result<()> {
+    let image_names = get_image_names(&docker_compose_cli, &service_names)?;
+    let compose_config = command::stdout_utf8(docker_compose_cli.command().arg("config"))?;
+
+    if dry_run {
+        log::info!("Would create bundle {out:?} with images {image_names:?}.");
+        return Ok(());
+    }
+
+    let manifest = Manifest {
+        images: image_names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| Image {
+                name: name.clone(),
+                path: format!("images/{index}.tar"),
+            })
+            .collect(),
+        kerek_version: env!("CARGO_PKG_VERSION").into(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+    let out = path::Path::new(&out);
+    let mut writer = Writer::create(out).with_context(|| format!("Unable to create {out:?}"))?;
+    tar::write_file(
+        &mut writer,
+        super::MANIFEST_PATH,
+        manifest.len().try_into()?,
+        &mut &manifest[..],
+    )?;
+    tar::write_file(
+        &mut writer,
+        super::COMPOSE_PATH,
+        compose_config.len().try_into()?,
+        &mut compose_config.as_bytes(),
+    )?;
+
+    let folder = temp::Dir::new()?;
+    let image_path = folder.path().join("image.tar");
+
+    for (index, name) in image_names.iter().enumerate() {
+        log::info!("Saving image {name:?}.");
+        command::status_ok(
+            docker_cli
+                .command()
+                .args(["save", "--output"])
+                .arg(&image_path)
+                .args(["--", name]),
+        )?;
+        tar::write_file(
+            &mut writer,
+            &format!("images/{index}.tar"),
+            fs::metadata(&image_path)?.len(),
+            &mut fs::File::open(&image_path)?,
+        )?;
+        fs::remove_file(&image_path)?;
+    }
+
+    tar::write_end(&mut writer)?;
+    writer
+        .finish()
+        .with_context(|| format!("Unable to finish {out:?}"))?;
+
+    let image_count = image_names.len();
+    log::info!("Created bundle {out:?} with {image_count} images.");
+    Ok(())
+}
+
+pub struct In<'a> {
+    pub docker_cli: docker::Cli<'a>,
+    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub dry_run: bool,
+    pub out: String,
+    pub service_names: Vec<String>,
+}
+
+fn get_image_names(
+    docker_compose_cli: &docker_compose::Cli,
+    service_names: &[String],
+) -> anyhow::Result<Vec<String>> {
+    let images = command::stdout_utf8(
+        docker_compose_cli
+            .command()
+            .args(["config", "--images", "--"])
+            .args(service_names),
+    )?;
+    let mut seen_images = collections::HashSet::new();
+
+    Ok(images
+        .lines()
+        .filter(|image| seen_images.insert(*image))
+        .map(|image| image.into())
+        .collect())
+}
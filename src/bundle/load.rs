@@ -0,0 +1,125 @@
+use super::Manifest;
+use crate::command;
+use crate::deploy;
+use crate::docker;
+use crate::docker_compose;
+use crate::log;
+use crate::tar;
+use crate::temp;
+use crate::transfer_images;
+use anyhow::Context;
+use std::collections;
+use std::fs;
+use std::io;
+use std::path;
+
+pub fn go(
+    In {
+        bundle,
+        compose_command,
+        deploy,
+        docker_arguments,
+        docker_cli,
+        docker_compose_arguments,
+        dry_run,
+    }: In,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        docker_compose_arguments.file.is_empty(),
+        "Option `--file` is not supported as the bundle has the Compose file"
+    );
+
+    let available_images = transfer_images::get_available_images(&docker_cli)
+        .context("Unable to check available images")?;
+    let folder = temp::Dir::new()?;
+    let compose_path = folder.path().join(super::COMPOSE_PATH);
+    let mut image_names = None::<collections::HashMap<String, String>>;
+
+    let mut bundle_reader = super::open(path::Path::new(&bundle))
+        .with_context(|| format!("Unable to open bundle {bundle:?}"))?;
+
+    tar::read_stream(&mut bundle_reader, |path, data| {
+        if path == super::MANIFEST_PATH {
+            let Manifest {
+                images,
+                kerek_version,
+            } = serde_json::from_reader(data).context("Unable to parse bundle manifest")?;
+            log::debug!("Bundle was created by Kerek {kerek_version}.");
+            image_names = Some(
+                images
+                    .into_iter()
+                    .map(|image| (image.path, image.name))
+                    .collect(),
+            );
+        } else if path == super::COMPOSE_PATH {
+            io::copy(data, &mut fs::File::create(&compose_path)?)?;
+        } else {
+            let image_names = image_names
+                .as_ref()
+                .context("Bundle manifest must come first")?;
+            let name = image_names
+                .get(path)
+                .with_context(|| format!("Bundle entry {path:?} is not in manifest"))?;
+
+            if available_images.contains_key(name) {
+                log::info!("Skipping image {name:?} as found.");
+            } else if dry_run {
+                log::info!("Would load image {name:?}.");
+            } else {
+                log::info!("Loading image {name:?}.");
+                command::stdin_copied_ok(data, docker_cli.command().arg("load"))
+                    .with_context(|| format!("Unable to load image {name:?}"))?;
+            }
+        }
+        Ok(())
+    })
+    .with_context(|| format!("Unable to read bundle {bundle:?}"))?;
+
+    if !deploy {
+        return Ok(());
+    }
+
+    let compose_path = compose_path
+        .to_str()
+        .context("Temporary path is not valid UTF-8")?;
+    let files = [compose_path.into()];
+    deploy::go(deploy::In {
+        build: false,
+        docker_cli,
+        docker_compose_cli: docker_compose::Cli::new(
+            &compose_command,
+            docker_arguments,
+            docker_compose::Arguments {
+                file: &files,
+                ..docker_compose_arguments
+            },
+        ),
+        dry_run,
+        force_recreate: false,
+        hosts: vec![],
+        max_unavailable_hosts: 1,
+        no_build: true,
+        no_deps: false,
+        no_start: false,
+        prune_images: None,
+        pull: Some("never".into()),
+        quiet_pull: false,
+        remove_orphans: false,
+        renew_anon_volumes: false,
+        service_names: Default::default(),
+        timeout: None,
+        transfer_images: false,
+        wait: false,
+        wait_timeout: None,
+    })
+}
+
+pub struct In<'a> {
+    pub bundle: String,
+    pub compose_command: Vec<String>,
+    pub deploy: bool,
+    pub docker_arguments: docker::Arguments<'a>,
+    pub docker_cli: docker::Cli<'a>,
+    pub docker_compose_arguments: docker_compose::Arguments<'a>,
+    pub dry_run: bool,
+}
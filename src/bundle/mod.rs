@@ -0,0 +1,116 @@
+pub mod create;
+pub mod load;
+
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+
+const COMPOSE_PATH: &str = "compose.yaml";
+const MANIFEST_PATH: &str = "manifest.json";
+
+// Bundle is a tar archive with the manifest first, then the resolved Compose
+// config, then one archive per image as by `docker save`.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Manifest {
+    images: Vec<Image>,
+    kerek_version: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Image {
+    name: String,
+    path: String,
+}
+
+enum Writer {
+    Gzip(flate2::write::GzEncoder<io::BufWriter<fs::File>>),
+    Plain(io::BufWriter<fs::File>),
+    Zstd(zstd::stream::write::Encoder<'static, io::BufWriter<fs::File>>),
+}
+
+impl Writer {
+    // Compression follows file extension.
+    fn create(path: &path::Path) -> io::Result<Self> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        Ok(match extension {
+            Some("gz" | "tgz") => Self::Gzip(flate2::write::GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            )),
+            Some("zst" | "tzst") => Self::Zstd(zstd::stream::write::Encoder::new(
+                file,
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+            )?),
+            _ => Self::Plain(file),
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Self::Gzip(encoder) => encoder.finish()?,
+            Self::Plain(file) => file,
+            Self::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl io::Write for Writer {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Gzip(encoder) => encoder.write(buffer),
+            Self::Plain(file) => file.write(buffer),
+            Self::Zstd(encoder) => encoder.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Plain(file) => file.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Compression is detected by magic number.
+fn open(path: &path::Path) -> io::Result<Box<dyn io::Read>> {
+    let mut file = io::BufReader::new(fs::File::open(path)?);
+    let magic_number = file.fill_buf()?;
+
+    Ok(if magic_number.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::bufread::MultiGzDecoder::new(file))
+    } else if magic_number.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::stream::read::Decoder::with_buffer(file)?)
+    } else {
+        Box::new(file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp;
+    use std::io::Read;
+
+    #[test_case::test_case("bundle.tar"; "plain")]
+    #[test_case::test_case("bundle.tar.gz"; "gzip")]
+    #[test_case::test_case("bundle.tar.zst"; "zstd")]
+    fn round_trips(file_name: &str) -> anyhow::Result<()> {
+        let folder = temp::Dir::new()?;
+        let path = folder.path().join(file_name);
+
+        let mut writer = Writer::create(&path)?;
+        writer.write_all(b"Hi")?;
+        writer.finish()?;
+
+        let mut contents = String::new();
+        open(&path)?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "Hi");
+        Ok(())
+    }
+}
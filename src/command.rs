@@ -1,11 +1,28 @@
 use super::log;
 use anyhow::Context;
 use serde::de;
+use std::error;
+use std::fmt;
 use std::io;
+use std::io::Read;
 use std::io::Write;
 use std::process;
+use std::sync;
+use std::sync::mpsc;
 use std::thread;
 
+const CHUNK_SIZE: usize = 1 << 16;
+const CHUNK_QUEUE_BOUND: usize = 64;
+
+pub fn is_success(command: &mut process::Command) -> bool {
+    command
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
 pub fn piped_ok<'a, T: IntoIterator<Item = &'a mut process::Command>>(
     commands: T,
 ) -> anyhow::Result<()> {
@@ -66,20 +83,230 @@ pub fn piped_ok<'a, T: IntoIterator<Item = &'a mut process::Command>>(
     }
 }
 
+// Like `piped_ok` but interposes a pipe after the first command that reports
+// the number of bytes passing through it.
+pub fn piped_counted_ok<'a, T: IntoIterator<Item = &'a mut process::Command>>(
+    commands: T,
+    mut count: impl FnMut(u64) + Send,
+) -> anyhow::Result<()> {
+    let mut commands = commands.into_iter().collect::<Vec<_>>();
+
+    let [first_command, last_commands @ ..] = &mut commands[..] else {
+        return Ok(());
+    };
+    if last_commands.is_empty() {
+        return status_ok(first_command);
+    }
+
+    first_command.stdout(process::Stdio::piped());
+    let mut first_process = Process {
+        child: first_command.spawn().command_context(first_command)?,
+        command: first_command,
+    };
+    let mut source = first_process
+        .child
+        .stdout
+        .take()
+        .context("Unable to open stdout")
+        .command_context(first_process.command)?;
+
+    let (reader, mut writer) = io::pipe().context("Unable to create pipe")?;
+    last_commands[0].stdin(reader);
+
+    thread::scope(|scope| {
+        let copy = scope.spawn(move || {
+            let mut buffer = vec![0; CHUNK_SIZE];
+            loop {
+                let size = source.read(&mut buffer)?;
+                if size == 0 {
+                    break io::Result::Ok(());
+                }
+                writer.write_all(&buffer[..size])?;
+                count(size.try_into().unwrap_or(u64::MAX));
+            }
+        });
+
+        let last_result = piped_ok(last_commands.iter_mut().map(|command| &mut **command));
+        // Close read end of pipe in case copying is blocked on writing.
+        last_commands[0].stdin(process::Stdio::null());
+        // As with `piped_ok`, prefer error of first command as root cause. If
+        // it is still running, it is killed as copying may be blocked on it.
+        let Process { child, command } = &mut first_process;
+        let early_first_status = if last_result.is_err() {
+            let status = child.try_wait();
+            if let Ok(None) = status {
+                let _ = child.kill();
+            }
+            Some(status)
+        } else {
+            None
+        };
+        let copy_result = copy
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Copying panicked")));
+
+        if let Some(status) = early_first_status {
+            // Reaps the first command in any case.
+            child.wait().command_context(command)?;
+            if let Some(status) = status.command_context(command)? {
+                status_result(status).command_context(command)?;
+            }
+            return last_result;
+        }
+
+        let first_status = child.wait().command_context(command)?;
+        status_result(first_status).command_context(command)?;
+        copy_result.context("Unable to pipe stdout")
+    })
+}
+
+// Pipes the output of the first commands through an in-process filter like a
+// compressor, then into each of the sinks concurrently. The outer result is
+// about the first commands, the inner results are about the respective sinks.
+pub fn piped_fan_out_ok<
+    'a,
+    T: IntoIterator<Item = &'a mut process::Command>,
+    U: Read,
+    F: FnOnce(process::ChildStdout) -> io::Result<U>,
+>(
+    commands: T,
+    filter: F,
+    sinks: Vec<&mut process::Command>,
+) -> anyhow::Result<Vec<anyhow::Result<()>>> {
+    let mut stdio = None;
+    let mut processes = vec![];
+
+    for command in commands {
+        if let Some(stdin) = stdio {
+            command.stdin(stdin);
+        }
+        command.stdout(process::Stdio::piped());
+
+        let mut process = Process {
+            child: command.spawn().command_context(command)?,
+            command,
+        };
+
+        stdio = Some(
+            process
+                .child
+                .stdout
+                .take()
+                .context("Unable to open stdout")
+                .command_context(command)?,
+        );
+
+        processes.push(process);
+    }
+
+    let mut source =
+        filter(stdio.context("No command to pipe from")?).context("Unable to set up filter")?;
+
+    let (read_result, sink_results) = thread::scope(|scope| {
+        let sinks = sinks
+            .into_iter()
+            .map(|command| {
+                let (sender, receiver) = mpsc::sync_channel::<sync::Arc<[u8]>>(CHUNK_QUEUE_BOUND);
+                let sink = scope.spawn(move || {
+                    (|| {
+                        let mut child = command.stdin(process::Stdio::piped()).spawn()?;
+                        let mut stdin = child.stdin.take().context("Unable to open stdin")?;
+
+                        // Keep receiving after a write error to not block sender.
+                        let mut write_result = Ok(());
+                        for chunk in receiver {
+                            if write_result.is_ok() {
+                                write_result = stdin.write_all(&chunk);
+                            }
+                        }
+                        drop(stdin);
+
+                        status_result(child.wait().context("Unable to wait")?)?;
+                        write_result.context("Unable to write to stdin")
+                    })()
+                    .command_context(command)
+                });
+                (sender, sink)
+            })
+            .collect::<Vec<_>>();
+
+        let read_result = (|| {
+            let mut buffer = vec![0; CHUNK_SIZE];
+            loop {
+                let count = source.read(&mut buffer)?;
+                if count == 0 {
+                    break io::Result::Ok(());
+                }
+                let chunk = sync::Arc::<[u8]>::from(&buffer[..count]);
+                for (sender, _) in &sinks {
+                    // A sink that stopped early reports its own error.
+                    let _ = sender.send(chunk.clone());
+                }
+            }
+        })()
+        .context("Unable to read stdout");
+
+        let sink_results = sinks
+            .into_iter()
+            .map(|(sender, sink)| {
+                drop(sender);
+                sink.join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Sink panicked")))
+            })
+            .collect::<Vec<_>>();
+
+        (read_result, sink_results)
+    });
+
+    for Process {
+        ref mut child,
+        command,
+    } in processes
+    {
+        status_result(child.wait().command_context(command)?).command_context(command)?;
+    }
+    read_result?;
+
+    Ok(sink_results)
+}
+
 pub fn status_ok(command: &mut process::Command) -> anyhow::Result<()> {
     (|| status_result(command.status()?))().command_context(command)
 }
 
-pub fn stdin_ok(input: &'static [u8], command: &mut process::Command) -> anyhow::Result<()> {
+pub fn stdin_ok<T: AsRef<[u8]> + Send + 'static>(
+    input: T,
+    command: &mut process::Command,
+) -> anyhow::Result<()> {
     (|| {
         let mut child = command.stdin(process::Stdio::piped()).spawn()?;
         let mut stdin = child.stdin.take().context("Unable to open stdin")?;
-        thread::spawn(move || stdin.write_all(input).context("Unable to write to stdin"));
+        thread::spawn(move || {
+            stdin
+                .write_all(input.as_ref())
+                .context("Unable to write to stdin")
+        });
         status_result(child.wait().context("Unable to wait")?)
     })()
     .command_context(command)
 }
 
+// Like `stdin_ok` but streams input from a reader.
+pub fn stdin_copied_ok<T: Read + ?Sized>(
+    input: &mut T,
+    command: &mut process::Command,
+) -> anyhow::Result<()> {
+    (|| {
+        let mut child = command.stdin(process::Stdio::piped()).spawn()?;
+        let mut stdin = child.stdin.take().context("Unable to open stdin")?;
+        let copy_result = io::copy(input, &mut stdin);
+        drop(stdin);
+        status_result(child.wait().context("Unable to wait")?)?;
+        copy_result.context("Unable to write to stdin").map(|_| ())
+    })()
+    .command_context(command)
+}
+
 pub fn stdout_json<T: de::DeserializeOwned>(command: &mut process::Command) -> anyhow::Result<T> {
     (|| {
         let output = command.stderr(process::Stdio::inherit()).output()?;
@@ -167,17 +394,37 @@ impl Drop for Process<'_> {
     }
 }
 
+// Unsuccessful exit of a command, keeping its status for callers to inspect.
+#[derive(Debug)]
+pub struct ExitError(pub process::ExitStatus);
+
+impl fmt::Display for ExitError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.0)
+    }
+}
+
+impl error::Error for ExitError {}
+
 fn status_result(status: process::ExitStatus) -> anyhow::Result<()> {
     if status.success() {
         Ok(())
     } else {
-        Err(anyhow::anyhow!("{status}"))
+        Err(ExitError(status).into())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test_case::test_case(invalid_program_(), false; "invalid program")]
+    #[test_case::test_case(bash("true"), true; "success")]
+    #[test_case::test_case(bash("echo 'Hi'; false"), false; "failure")]
+    fn is_success_handles(mut command: process::Command, expected: bool) {
+        assert_eq!(is_success(&mut command), expected)
+    }
+
     #[test_case::test_case(&[], true; "0")]
     #[test_case::test_case(&[""], false; "invalid 1")]
     #[test_case::test_case(&["false"], false; "failure 1")]
@@ -211,6 +458,85 @@ mod tests {
         assert_eq!(piped_ok(commands.iter_mut()).is_ok(), expected)
     }
 
+    #[test_case::test_case(&[], Some(0); "0")]
+    #[test_case::test_case(&["true"], Some(0); "1")]
+    #[test_case::test_case(&["echo 'Hi'", "[[ $(cat) == 'Hi' ]]"], Some(3); "2")]
+    #[test_case::test_case(&["echo 'Hi'", "rev", "[[ $(cat) == 'iH' ]]"], Some(3); "3")]
+    #[test_case::test_case(&["echo 'Hi'", "false"], None; "failure after count")]
+    #[test_case::test_case(&["", "cat"], None; "invalid first")]
+    #[test_case::test_case(
+        &["head --bytes 1000000 /dev/zero", "head --bytes 1"],
+        None;
+        "large with early exit"
+    )]
+    #[test_case::test_case(&["sleep 10", "false"], None; "failure sink with running source")]
+    fn piped_counted_ok_handles(commands: &[&str], expected: Option<u64>) {
+        let mut commands = commands
+            .iter()
+            .map(|command| bash_or_invalid(command))
+            .collect::<Vec<_>>();
+        let mut count = 0;
+
+        let result = piped_counted_ok(commands.iter_mut(), |size| count += size);
+
+        assert_eq!(result.ok().map(|()| count), expected)
+    }
+
+    #[test_case::test_case(&[], &[], None; "0")]
+    #[test_case::test_case(&[""], &["true"], None; "invalid source")]
+    #[test_case::test_case(&["false"], &["true"], None; "failure source")]
+    #[test_case::test_case(&["echo 'Hi'"], &[], Some(&[]); "no sinks")]
+    #[test_case::test_case(
+        &["echo 'Hi'"],
+        &["[[ $(cat) == 'Hi' ]]", "[[ $(cat) == 'Hi' ]]"],
+        Some(&[true, true]);
+        "pipe 1 to 2"
+    )]
+    #[test_case::test_case(
+        &["echo 'Hi'", "rev"],
+        &["[[ $(cat) == 'iH' ]]", "false", ""],
+        Some(&[true, false, false]);
+        "pipe 2 to 3"
+    )]
+    #[test_case::test_case(
+        &["head --bytes 1000000 /dev/zero"],
+        &["[[ $(wc --bytes) == 1000000 ]]", "true"],
+        Some(&[true, false]);
+        "large with early sink exit"
+    )]
+    fn piped_fan_out_ok_handles(commands: &[&str], sinks: &[&str], expected: Option<&[bool]>) {
+        let mut commands = commands
+            .iter()
+            .map(|command| bash_or_invalid(command))
+            .collect::<Vec<_>>();
+        let mut sinks = sinks
+            .iter()
+            .map(|sink| bash_or_invalid(sink))
+            .collect::<Vec<_>>();
+
+        let results = piped_fan_out_ok(commands.iter_mut(), Ok, sinks.iter_mut().collect()).ok();
+
+        assert_eq!(
+            results.map(|results| results
+                .iter()
+                .map(|result| result.is_ok())
+                .collect::<Vec<_>>()),
+            expected.map(|expected| expected.to_vec()),
+        )
+    }
+
+    #[test]
+    fn piped_fan_out_ok_filters() -> anyhow::Result<()> {
+        let results = piped_fan_out_ok(
+            [&mut bash("echo 'Hi'")],
+            |stdout| Ok(stdout.chain("there".as_bytes())),
+            vec![&mut bash("[[ $(cat) == $'Hi\\nthere' ]]")],
+        )?;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        Ok(())
+    }
+
     #[test_case::test_case(invalid_program_(), false; "invalid program")]
     #[test_case::test_case(bash("true"), true; "success")]
     #[test_case::test_case(bash("false"), false; "failure")]
@@ -225,6 +551,16 @@ mod tests {
         assert_eq!(stdin_ok("Hi".as_bytes(), &mut command).is_ok(), expected)
     }
 
+    #[test_case::test_case(invalid_program_(), false; "invalid program")]
+    #[test_case::test_case(bash("[[ $(cat) == 'Hi' ]]"), true; "success")]
+    #[test_case::test_case(bash("[[ $(cat) != 'Hi' ]]"), false; "failure")]
+    fn stdin_copied_ok_handles(mut command: process::Command, expected: bool) {
+        assert_eq!(
+            stdin_copied_ok(&mut "Hi".as_bytes(), &mut command).is_ok(),
+            expected
+        )
+    }
+
     #[test_case::test_case(invalid_program_(), None; "invalid program")]
     #[test_case::test_case(bash("false"), None; "failure")]
     #[test_case::test_case(bash("echo '\"Hi\"'"), Some("Hi".into()); "success")]
@@ -253,6 +589,14 @@ mod tests {
         assert_eq!(stdout_utf8(&mut command).ok(), expected)
     }
 
+    fn bash_or_invalid(script: &str) -> process::Command {
+        if script.is_empty() {
+            invalid_program_()
+        } else {
+            bash(script)
+        }
+    }
+
     fn invalid_program_() -> process::Command {
         process::Command::new("")
     }
@@ -0,0 +1,64 @@
+use super::model;
+
+pub fn go(
+    actual_containers: &model::ActualContainers,
+    desired_services: &model::DesiredServices,
+) -> anyhow::Result<()> {
+    for (service_name, service_definition) in desired_services {
+        let ready_count = actual_containers
+            .iter()
+            .filter(|container| {
+                &container.service_name == service_name
+                    && container.service_config_hash == service_definition.service_config_hash
+                    && container.is_running
+                    && container
+                        .health
+                        .as_ref()
+                        .is_none_or(|health| health == "healthy")
+            })
+            .count();
+        let replica_count = service_definition.replica_count;
+
+        if ready_count < replica_count.into() {
+            return Err(anyhow::anyhow!(
+                "Service {service_name:?} has {ready_count} of {replica_count} replicas ready"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(true, None, "a", true; "running")]
+    #[test_case::test_case(true, Some("healthy"), "a", true; "healthy")]
+    #[test_case::test_case(true, Some("starting"), "a", false; "starting")]
+    #[test_case::test_case(true, Some("unhealthy"), "a", false; "unhealthy")]
+    #[test_case::test_case(false, None, "a", false; "stopped")]
+    #[test_case::test_case(true, None, "b", false; "stale")]
+    fn handles(is_running: bool, health: Option<&str>, service_config_hash: &str, expected: bool) {
+        let actual_containers = [model::ActualContainer {
+            container_id: "0".into(),
+            health: health.map(|health| health.into()),
+            image_id: "i".into(),
+            is_running,
+            service_config_hash: service_config_hash.into(),
+            service_name: "X".into(),
+        }]
+        .into();
+        let desired_services = [(
+            "X".into(),
+            model::DesiredServiceDefinition {
+                replica_count: 1,
+                service_config_hash: "a".into(),
+                update_order: model::OperationOrder::StopFirst,
+            },
+        )]
+        .into();
+
+        assert_eq!(go(&actual_containers, &desired_services).is_ok(), expected)
+    }
+}
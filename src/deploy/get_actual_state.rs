@@ -36,6 +36,8 @@ pub fn go(
 struct Container {
     config: Config,
     id: String,
+    image: String,
+    state: State,
 }
 
 #[derive(serde::Deserialize)]
@@ -44,9 +46,25 @@ struct Config {
     labels: collections::BTreeMap<String, String>,
 }
 
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct State {
+    health: Option<Health>,
+    running: bool,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Health {
+    status: String,
+}
+
 fn convert_container(container: Container) -> model::ActualContainer {
     model::ActualContainer {
         container_id: container.id,
+        health: container.state.health.map(|health| health.status),
+        image_id: docker::normalize_image_id(&container.image),
+        is_running: container.state.running,
         service_config_hash: container.config.labels["com.docker.compose.config-hash"].clone(),
         service_name: container.config.labels["com.docker.compose.service"].clone(),
     }
@@ -1,12 +1,23 @@
 mod apply_changes;
-mod get_actual_state;
+mod check_health;
+pub mod get_actual_state;
 mod get_desired_state;
-mod model;
-mod plan_changes;
+pub mod model;
+pub mod plan_changes;
+mod roll_out;
 
+use super::command;
 use super::docker;
 use super::docker_compose;
+use super::log;
+use super::prune;
+use super::transfer_images;
+use anyhow::Context;
 use std::collections;
+use std::thread;
+use std::time;
+
+const DEFAULT_HEALTH_TIMEOUT_SECONDS: u64 = 60;
 
 pub fn go(
     In {
@@ -15,38 +26,61 @@ pub fn go(
         docker_compose_cli,
         dry_run,
         force_recreate,
+        hosts,
+        max_unavailable_hosts,
         no_build,
         no_deps,
         no_start,
+        prune_images,
         pull,
         quiet_pull,
         remove_orphans,
         renew_anon_volumes,
         service_names,
         timeout,
+        transfer_images,
         wait,
         wait_timeout,
     }: In,
 ) -> anyhow::Result<()> {
-    let desired_services = get_desired_state::go(&service_names, &docker_compose_cli, no_deps)?;
-    let service_names = if service_names.is_empty() {
-        vec![]
-    } else {
-        desired_services.keys().collect()
-    };
-    let actual_containers = get_actual_state::go(&service_names, &docker_cli, &docker_compose_cli)?;
-    let changes = plan_changes::go(&actual_containers, &desired_services, force_recreate);
+    if hosts.is_empty() {
+        probe(&docker_cli, &docker_compose_cli)?;
+    }
+    for host in &hosts {
+        probe(
+            &docker_cli.with_host(host),
+            &docker_compose_cli.with_host(host),
+        )
+        .with_context(|| format!("Unable to probe host {host:?}"))?;
+    }
 
-    apply_changes::go(apply_changes::In {
-        actual_containers: &actual_containers,
-        build,
-        changes: &changes,
-        docker_cli: &docker_cli,
-        docker_compose_cli: &docker_compose_cli,
+    if transfer_images {
+        anyhow::ensure!(
+            pull.as_deref().is_none_or(|pull| pull == "never"),
+            "Option `--transfer-images` deploys with `--pull never`"
+        );
+        transfer_missing_images(
+            &service_names,
+            build,
+            dry_run,
+            &docker_cli,
+            &docker_compose_cli,
+            &hosts,
+        )?;
+    }
+
+    let options = Options {
+        build: build && !transfer_images,
         dry_run,
-        no_build,
+        force_recreate,
+        no_build: no_build || transfer_images,
+        no_deps,
         no_start,
-        pull: pull.as_deref(),
+        pull: if transfer_images {
+            Some("never")
+        } else {
+            pull.as_deref()
+        },
         quiet_pull,
         remove_orphans,
         renew_anon_volumes,
@@ -54,7 +88,29 @@ pub fn go(
         timeout: timeout.as_deref(),
         wait,
         wait_timeout: wait_timeout.as_deref(),
-    })
+    };
+
+    // A single host is deployed to alike however given, without waiting for
+    // health as no other host depends on it.
+    let (docker_cli, docker_compose_cli) = match &hosts[..] {
+        [] => (docker_cli, docker_compose_cli),
+        [host] => (
+            docker_cli.with_host(host),
+            docker_compose_cli.with_host(host),
+        ),
+        _ => {
+            return roll_out::go(&hosts, max_unavailable_hosts, |host| {
+                let docker_cli = docker_cli.with_host(host);
+                let docker_compose_cli = docker_compose_cli.with_host(host);
+                log::info!("Deploying to host {host:?}.");
+                deploy(&options, &docker_cli, &docker_compose_cli)?;
+                wait_until_healthy(&options, &docker_cli, &docker_compose_cli)?;
+                prune_unused_images(prune_images, dry_run, &docker_cli, &docker_compose_cli)
+            });
+        }
+    };
+    deploy(&options, &docker_cli, &docker_compose_cli)?;
+    prune_unused_images(prune_images, dry_run, &docker_cli, &docker_compose_cli)
 }
 
 pub struct In<'a> {
@@ -63,15 +119,238 @@ pub struct In<'a> {
     pub docker_compose_cli: docker_compose::Cli<'a>,
     pub dry_run: bool,
     pub force_recreate: bool,
+    pub hosts: Vec<String>,
+    pub max_unavailable_hosts: u16,
     pub no_build: bool,
     pub no_deps: bool,
     pub no_start: bool,
+    pub prune_images: Option<u16>,
     pub pull: Option<String>,
     pub quiet_pull: bool,
     pub remove_orphans: bool,
     pub renew_anon_volumes: bool,
     pub service_names: collections::BTreeSet<String>,
     pub timeout: Option<String>,
+    pub transfer_images: bool,
     pub wait: bool,
     pub wait_timeout: Option<String>,
 }
+
+// Builds images on the default daemon if requested and pulls images missing
+// there, then transfers those missing on the target hosts, so deploying never
+// needs a registry.
+fn transfer_missing_images(
+    service_names: &collections::BTreeSet<String>,
+    build: bool,
+    dry_run: bool,
+    docker_cli: &docker::Cli,
+    docker_compose_cli: &docker_compose::Cli,
+    hosts: &[String],
+) -> anyhow::Result<()> {
+    let default_docker_compose_cli = docker_compose_cli.default_daemon();
+
+    if build {
+        log::info!("Building images locally.");
+        command::status_ok(
+            default_docker_compose_cli
+                .command()
+                .args(dry_run.then_some("--dry-run").iter())
+                .args(["build", "--"])
+                .args(service_names),
+        )
+        .context("Unable to build images")?;
+    }
+
+    log::info!("Pulling images missing locally.");
+    command::status_ok(
+        default_docker_compose_cli
+            .command()
+            .args(dry_run.then_some("--dry-run").iter())
+            .args([
+                "pull",
+                "--ignore-buildable",
+                "--policy",
+                "missing",
+                "--quiet",
+                "--",
+            ])
+            .args(service_names),
+    )
+    .context("Unable to pull images")?;
+
+    transfer_images::go(transfer_images::In {
+        compress: vec![],
+        delta: false,
+        direct: false,
+        docker_cli: *docker_cli,
+        docker_compose_cli: Some(*docker_compose_cli),
+        dry_run,
+        force: false,
+        hosts: hosts.to_vec(),
+        images: service_names.iter().cloned().collect(),
+        resumable: false,
+        source_host: None,
+        ssh_cli: None,
+    })
+    .context("Unable to transfer images")
+}
+
+fn prune_unused_images(
+    keep_revisions: Option<u16>,
+    dry_run: bool,
+    docker_cli: &docker::Cli,
+    docker_compose_cli: &docker_compose::Cli,
+) -> anyhow::Result<()> {
+    match keep_revisions {
+        None => Ok(()),
+        Some(keep_revisions) => prune::remove_unused_images(prune::In {
+            docker_cli: *docker_cli,
+            docker_compose_cli: *docker_compose_cli,
+            dry_run,
+            keep_revisions,
+        })
+        .context("Unable to prune images"),
+    }
+}
+
+struct Options<'a> {
+    build: bool,
+    dry_run: bool,
+    force_recreate: bool,
+    no_build: bool,
+    no_deps: bool,
+    no_start: bool,
+    pull: Option<&'a str>,
+    quiet_pull: bool,
+    remove_orphans: bool,
+    renew_anon_volumes: bool,
+    service_names: &'a collections::BTreeSet<String>,
+    timeout: Option<&'a str>,
+    wait: bool,
+    wait_timeout: Option<&'a str>,
+}
+
+// Probes the container engine and Compose once, failing early if unsupported.
+pub fn probe(
+    docker_cli: &docker::Cli,
+    docker_compose_cli: &docker_compose::Cli,
+) -> anyhow::Result<docker::Engine> {
+    let engine = docker_cli
+        .probe(Some(docker_compose_cli))
+        .context("Unable to probe container engine")?;
+    if let Some(compose_version) = &engine.compose_version {
+        docker_compose::check_version(compose_version)?;
+    }
+    Ok(engine)
+}
+
+pub fn get_state(
+    service_names: &collections::BTreeSet<String>,
+    docker_cli: &docker::Cli,
+    docker_compose_cli: &docker_compose::Cli,
+    no_deps: bool,
+) -> anyhow::Result<(model::ActualContainers, model::DesiredServices)> {
+    let desired_services = get_desired_state::go(service_names, docker_compose_cli, no_deps)?;
+    let service_names = if service_names.is_empty() {
+        vec![]
+    } else {
+        desired_services.keys().collect()
+    };
+    let actual_containers = get_actual_state::go(&service_names, docker_cli, docker_compose_cli)?;
+
+    Ok((actual_containers, desired_services))
+}
+
+fn deploy(
+    &Options {
+        build,
+        dry_run,
+        force_recreate,
+        no_build,
+        no_deps,
+        no_start,
+        pull,
+        quiet_pull,
+        remove_orphans,
+        renew_anon_volumes,
+        service_names,
+        timeout,
+        wait,
+        wait_timeout,
+    }: &Options,
+    docker_cli: &docker::Cli,
+    docker_compose_cli: &docker_compose::Cli,
+) -> anyhow::Result<()> {
+    let (actual_containers, desired_services) =
+        get_state(service_names, docker_cli, docker_compose_cli, no_deps)?;
+    let service_names = if service_names.is_empty() {
+        vec![]
+    } else {
+        desired_services.keys().collect()
+    };
+    let changes = plan_changes::go(&actual_containers, &desired_services, force_recreate);
+
+    apply_changes::go(apply_changes::In {
+        actual_containers: &actual_containers,
+        build,
+        changes: &changes,
+        docker_cli,
+        docker_compose_cli,
+        dry_run,
+        no_build,
+        no_start,
+        pull,
+        quiet_pull,
+        remove_orphans,
+        renew_anon_volumes,
+        service_names: &service_names,
+        timeout,
+        wait,
+        wait_timeout,
+    })
+}
+
+fn wait_until_healthy(
+    &Options {
+        dry_run,
+        no_deps,
+        no_start,
+        service_names,
+        wait_timeout,
+        ..
+    }: &Options,
+    docker_cli: &docker::Cli,
+    docker_compose_cli: &docker_compose::Cli,
+) -> anyhow::Result<()> {
+    if dry_run || no_start {
+        return Ok(());
+    }
+
+    let timeout = time::Duration::from_secs(
+        wait_timeout
+            .and_then(|wait_timeout| wait_timeout.parse().ok())
+            .unwrap_or(DEFAULT_HEALTH_TIMEOUT_SECONDS),
+    );
+    let start = time::Instant::now();
+    // Only containers change meanwhile, not the Compose file.
+    let desired_services = get_desired_state::go(service_names, docker_compose_cli, no_deps)?;
+    let service_names = if service_names.is_empty() {
+        vec![]
+    } else {
+        desired_services.keys().collect()
+    };
+
+    loop {
+        let actual_containers =
+            get_actual_state::go(&service_names, docker_cli, docker_compose_cli)?;
+
+        match check_health::go(&actual_containers, &desired_services) {
+            Ok(()) => return Ok(()),
+            Err(error) if start.elapsed() < timeout => {
+                log::debug!("Waiting for health: {error}");
+                thread::sleep(time::Duration::from_secs(1));
+            }
+            Err(error) => return Err(error.context("Health check failed")),
+        }
+    }
+}
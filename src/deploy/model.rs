@@ -5,6 +5,9 @@ pub type ActualContainers = collections::BTreeSet<ActualContainer>;
 #[derive(Eq, Ord, PartialEq, PartialOrd)]
 pub struct ActualContainer {
     pub container_id: String,
+    pub health: Option<String>,
+    pub image_id: String,
+    pub is_running: bool,
     pub service_config_hash: String,
     pub service_name: String,
 }
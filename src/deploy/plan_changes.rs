@@ -51,6 +51,7 @@ fn service_container_removal(
         container_id,
         service_config_hash,
         service_name,
+        ..
     }: &model::ActualContainer,
 ) -> model::ServiceContainerChange {
     model::ServiceContainerChange::Remove {
@@ -257,6 +258,9 @@ mod tests {
             .split_whitespace()
             .map(|container| model::ActualContainer {
                 container_id: (&container[2..]).into(),
+                health: None,
+                image_id: "i".into(),
+                is_running: true,
                 service_config_hash: (&container[1..2]).into(),
                 service_name: (&container[..1]).into(),
             })
@@ -0,0 +1,146 @@
+use crate::log;
+use std::panic;
+use std::sync::mpsc;
+use std::thread;
+
+// Deploys to hosts in turn such that at most `max_unavailable_hosts` hosts are
+// unavailable at any time. A host is unavailable from the start of its
+// deployment until it is healthy, whereupon the next host starts. After a host
+// fails, no further hosts start, but the ones in progress are waited for, and
+// every failing host is reported.
+pub fn go<F: Fn(&str) -> anyhow::Result<()> + Sync>(
+    hosts: &[String],
+    max_unavailable_hosts: u16,
+    deploy_to_host: F,
+) -> anyhow::Result<()> {
+    let max_unavailable_hosts = usize::from(max_unavailable_hosts.max(1));
+    let deploy_to_host = &deploy_to_host;
+
+    let mut failures = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        let mut pending_hosts = hosts.iter();
+        let mut unavailable_host_count = 0;
+        let mut failures = vec![];
+
+        loop {
+            while unavailable_host_count < max_unavailable_hosts && failures.is_empty() {
+                let Some(host) = pending_hosts.next() else {
+                    break;
+                };
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let result =
+                        panic::catch_unwind(panic::AssertUnwindSafe(|| deploy_to_host(host)))
+                            .unwrap_or_else(|_| Err(anyhow::anyhow!("Deployment panicked")));
+                    // The receiver outlives all deployments.
+                    let _ = sender.send((host, result));
+                });
+                unavailable_host_count += 1;
+            }
+
+            if unavailable_host_count == 0 {
+                break;
+            }
+
+            let Ok((host, result)) = receiver.recv() else {
+                break;
+            };
+            unavailable_host_count -= 1;
+            if let Err(error) = result {
+                failures.push((host, error));
+            }
+        }
+
+        let skipped_host_count = pending_hosts.count();
+        if skipped_host_count > 0 {
+            log::warning!("Skipped {skipped_host_count} hosts after failure.");
+        }
+        failures
+    });
+
+    failures.sort_by_key(|(host, _)| hosts.iter().position(|other| other == *host));
+    match failures.len() {
+        0 => Ok(()),
+        1 => {
+            let (host, error) = failures.remove(0);
+            Err(error.context(format!("Unable to deploy to host {host:?}")))
+        }
+        failure_count => {
+            let details = failures
+                .iter()
+                .map(|(host, error)| format!("\n- {host:?}: {error:#}"))
+                .collect::<String>();
+            Err(anyhow::anyhow!(
+                "Unable to deploy to {failure_count} hosts:{details}"
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync;
+    use std::sync::atomic;
+
+    #[test_case::test_case(1, &[], &["a", "b", "c", "d"], None; "one at a time")]
+    #[test_case::test_case(2, &[], &["a", "b", "c", "d"], None; "two at a time")]
+    #[test_case::test_case(
+        1,
+        &["b"],
+        &["a", "b"],
+        Some("Unable to deploy to host \"b\": b failed");
+        "stop on failure"
+    )]
+    #[test_case::test_case(
+        2,
+        &["a", "b"],
+        &["a", "b"],
+        Some("Unable to deploy to 2 hosts:\n- \"a\": a failed\n- \"b\": b failed");
+        "report all failures"
+    )]
+    fn handles(
+        max_unavailable_hosts: u16,
+        failing_hosts: &[&str],
+        expected_started_hosts: &[&str],
+        expected_error: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let hosts = ["a", "b", "c", "d"].map(String::from);
+        let started_hosts = sync::Mutex::new(vec![]);
+        let unavailable_host_count = atomic::AtomicUsize::new(0);
+        let max_unavailable_host_count = atomic::AtomicUsize::new(0);
+        // Makes the first hosts overlap so that concurrency is observable.
+        let barrier = sync::Barrier::new(max_unavailable_hosts.into());
+
+        let result = go(&hosts, max_unavailable_hosts, |host| {
+            started_hosts
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Poisoned"))?
+                .push(host.to_string());
+            let count = unavailable_host_count.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+            max_unavailable_host_count.fetch_max(count, atomic::Ordering::SeqCst);
+            if host == "a" || host == "b" {
+                barrier.wait();
+            }
+            unavailable_host_count.fetch_sub(1, atomic::Ordering::SeqCst);
+
+            anyhow::ensure!(!failing_hosts.contains(&host), "{host} failed");
+            Ok(())
+        });
+
+        let mut started_hosts = started_hosts
+            .into_inner()
+            .map_err(|_| anyhow::anyhow!("Poisoned"))?;
+        started_hosts.sort();
+        assert_eq!(started_hosts, expected_started_hosts);
+        assert_eq!(
+            max_unavailable_host_count.into_inner(),
+            usize::from(max_unavailable_hosts),
+        );
+        assert_eq!(
+            result.err().map(|error| format!("{error:#}")),
+            expected_error.map(String::from),
+        );
+        Ok(())
+    }
+}
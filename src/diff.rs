@@ -0,0 +1,262 @@
+use super::deploy;
+use super::deploy::model;
+use super::deploy::plan_changes;
+use super::docker;
+use super::docker_compose;
+use std::collections;
+
+// Differs from exit status 1 of errors.
+pub const DRIFT_EXIT_CODE: i32 = 2;
+
+pub fn go(
+    In {
+        docker_cli,
+        docker_compose_cli,
+        format,
+        service_names,
+    }: In,
+) -> anyhow::Result<bool> {
+    deploy::probe(&docker_cli, &docker_compose_cli)?;
+    let (actual_containers, desired_services) = deploy::get_state(
+        &service_names,
+        &docker_cli,
+        &docker_compose_cli,
+        !service_names.is_empty(),
+    )?;
+    let changes = get_changes(
+        &actual_containers,
+        plan_changes::go(&actual_containers, &desired_services, false),
+    );
+
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string(&Diff {
+                changes: &changes,
+                has_drift: !changes.is_empty(),
+            })?,
+        ),
+        Format::Text => print!("{}", render(&changes)),
+    }
+
+    Ok(!changes.is_empty())
+}
+
+pub struct In<'a> {
+    pub docker_cli: docker::Cli<'a>,
+    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub format: Format,
+    pub service_names: collections::BTreeSet<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Format {
+    Json,
+    Text,
+}
+
+#[derive(serde::Serialize)]
+struct Diff<'a> {
+    changes: &'a [Change],
+    has_drift: bool,
+}
+
+// Planned change that counts as drift. Besides additions and removals, this
+// includes up-to-date containers that are not running, which `deploy` starts.
+#[derive(Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case", tag = "action")]
+enum Change {
+    Add {
+        service_config_hash: String,
+        service_name: String,
+    },
+    Remove {
+        container_id: String,
+        service_config_hash: String,
+        service_name: String,
+    },
+    Start {
+        container_id: String,
+        service_config_hash: String,
+        service_name: String,
+    },
+}
+
+fn get_changes(
+    actual_containers: &model::ActualContainers,
+    changes: Vec<model::ServiceContainerChange>,
+) -> Vec<Change> {
+    let stopped_container_ids = actual_containers
+        .iter()
+        .filter(|container| !container.is_running)
+        .map(|container| container.container_id.as_str())
+        .collect::<collections::BTreeSet<_>>();
+
+    changes
+        .into_iter()
+        .filter_map(|change| match change {
+            model::ServiceContainerChange::Add {
+                service_config_hash,
+                service_name,
+            } => Some(Change::Add {
+                service_config_hash,
+                service_name,
+            }),
+            model::ServiceContainerChange::Keep {
+                container_id,
+                service_config_hash,
+                service_name,
+            } => stopped_container_ids
+                .contains(container_id.as_str())
+                .then_some(Change::Start {
+                    container_id,
+                    service_config_hash,
+                    service_name,
+                }),
+            model::ServiceContainerChange::Remove {
+                container_id,
+                service_config_hash,
+                service_name,
+            } => Some(Change::Remove {
+                container_id,
+                service_config_hash,
+                service_name,
+            }),
+        })
+        .collect()
+}
+
+fn render(changes: &[Change]) -> String {
+    changes
+        .iter()
+        .map(|change| match change {
+            Change::Add {
+                service_config_hash,
+                service_name,
+            } => {
+                let service_config_hash = summarize_hash(service_config_hash);
+                format!("+ {service_name} (config hash {service_config_hash})\n")
+            }
+            Change::Remove {
+                container_id,
+                service_config_hash,
+                service_name,
+            } => {
+                let container_id = summarize_hash(container_id);
+                let service_config_hash = summarize_hash(service_config_hash);
+                format!(
+                    "- {service_name} container {container_id} \
+                    (config hash {service_config_hash})\n"
+                )
+            }
+            Change::Start {
+                container_id,
+                service_config_hash,
+                service_name,
+            } => {
+                let container_id = summarize_hash(container_id);
+                let service_config_hash = summarize_hash(service_config_hash);
+                format!(
+                    "! {service_name} container {container_id} \
+                    (config hash {service_config_hash}) is not running\n"
+                )
+            }
+        })
+        .collect()
+}
+
+fn summarize_hash(hash: &str) -> &str {
+    hash.get(..8).unwrap_or(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_changes_handles() {
+        let container = |container_id: &str, is_running| model::ActualContainer {
+            container_id: container_id.into(),
+            health: None,
+            image_id: "i".into(),
+            is_running,
+            service_config_hash: "h".into(),
+            service_name: "s".into(),
+        };
+        let keep = |container_id: &str| model::ServiceContainerChange::Keep {
+            container_id: container_id.into(),
+            service_config_hash: "h".into(),
+            service_name: "s".into(),
+        };
+
+        assert_eq!(
+            get_changes(
+                &[container("a", true), container("b", false)].into(),
+                vec![
+                    keep("a"),
+                    keep("b"),
+                    model::ServiceContainerChange::Add {
+                        service_config_hash: "h".into(),
+                        service_name: "s".into(),
+                    },
+                ],
+            ),
+            [
+                Change::Start {
+                    container_id: "b".into(),
+                    service_config_hash: "h".into(),
+                    service_name: "s".into(),
+                },
+                Change::Add {
+                    service_config_hash: "h".into(),
+                    service_name: "s".into(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn render_handles() {
+        assert_eq!(
+            render(&[
+                Change::Remove {
+                    container_id: "0123456789abcdef".into(),
+                    service_config_hash: "fedcba9876543210".into(),
+                    service_name: "greet".into(),
+                },
+                Change::Add {
+                    service_config_hash: "a".into(),
+                    service_name: "greet".into(),
+                },
+                Change::Start {
+                    container_id: "b".into(),
+                    service_config_hash: "c".into(),
+                    service_name: "reverse-proxy".into(),
+                },
+            ]),
+            "\
+- greet container 01234567 (config hash fedcba98)
++ greet (config hash a)
+! reverse-proxy container b (config hash c) is not running
+",
+        );
+    }
+
+    #[test]
+    fn json_handles() -> anyhow::Result<()> {
+        let changes = [Change::Remove {
+            container_id: "a".into(),
+            service_config_hash: "b".into(),
+            service_name: "c".into(),
+        }];
+
+        assert_eq!(
+            serde_json::to_string(&Diff {
+                changes: &changes,
+                has_drift: true,
+            })?,
+            r#"{"changes":[{"action":"remove","container_id":"a","service_config_hash":"b","service_name":"c"}],"has_drift":true}"#,
+        );
+        Ok(())
+    }
+}
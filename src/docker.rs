@@ -1,17 +1,28 @@
+use super::command;
+use super::docker_compose;
 use super::log;
+use super::ssh;
+use anyhow::Context;
+use std::env;
+use std::ffi;
+use std::fs;
+use std::path;
 use std::process;
 
+#[derive(Clone, Copy)]
 pub struct Cli<'a> {
     arguments: Arguments<'a>,
     container_engine: &'a str,
 }
 
+#[derive(Clone, Copy)]
 pub struct Arguments<'a> {
     pub config: Option<&'a str>,
     pub context: Option<&'a str>,
     pub debug: bool,
     pub host: Option<&'a str>,
     pub log_level: Option<log::Level>,
+    pub ssh_wrapper_folder: Option<&'a path::Path>,
     pub tls: bool,
     pub tlscacert: Option<&'a str>,
     pub tlscert: Option<&'a str>,
@@ -19,6 +30,81 @@ pub struct Arguments<'a> {
     pub tlsverify: bool,
 }
 
+impl<'a> Arguments<'a> {
+    // Returns `PATH` for the `ssh` wrapper, if any, unless the Docker host is
+    // evidently not an SSH host. This leaves out the `PATH` override otherwise
+    // shown in errors.
+    pub fn ssh_path(&self) -> Option<ffi::OsString> {
+        self.ssh_wrapper_folder
+            .filter(|_| self.may_use_ssh())
+            .and_then(ssh::wrapped_path)
+    }
+
+    // Whether the Docker host may be an SSH host, following the precedence of
+    // Docker: context, host, `DOCKER_CONTEXT`, `DOCKER_HOST`, and finally the
+    // current context set with `docker context use`.
+    pub fn may_use_ssh(&self) -> bool {
+        match (self.context, self.host) {
+            (Some(_), _) => true,
+            (None, Some(host)) => host.starts_with("ssh://"),
+            (None, None) => {
+                if env::var_os("DOCKER_CONTEXT").is_some() {
+                    true
+                } else if let Ok(host) = env::var("DOCKER_HOST") {
+                    host.starts_with("ssh://")
+                } else {
+                    self.get_current_context()
+                        .is_some_and(|context| context != "default")
+                }
+            }
+        }
+    }
+
+    fn get_current_context(&self) -> Option<String> {
+        let folder = match self.config {
+            None => env::var_os("DOCKER_CONFIG")
+                .map(path::PathBuf::from)
+                .or_else(|| {
+                    env::var_os("HOME").map(|home| path::Path::new(&home).join(".docker"))
+                })?,
+            Some(config) => path::PathBuf::from(config),
+        };
+        let config = fs::read_to_string(folder.join("config.json")).ok()?;
+        parse_current_context(&config)
+    }
+
+    pub fn default_daemon(self) -> Self {
+        Self {
+            context: None,
+            host: None,
+            ..self
+        }
+    }
+
+    pub fn with_host(self, host: &'a str) -> Self {
+        Self {
+            context: None,
+            host: Some(host),
+            ..self
+        }
+    }
+}
+
+pub struct Engine {
+    pub api_version: Option<String>,
+    pub compose_version: Option<String>,
+    pub kind: EngineKind,
+    pub rootless: Option<bool>,
+    pub version: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EngineKind {
+    Docker,
+    Podman,
+    Other,
+}
+
 impl<'a> Cli<'a> {
     pub fn new(container_engine: &'a str, arguments: Arguments<'a>) -> Self {
         Self {
@@ -29,6 +115,7 @@ impl<'a> Cli<'a> {
 
     pub fn command(&self) -> process::Command {
         let mut command = process::Command::new(self.container_engine);
+        command.envs(self.arguments.ssh_path().map(|path| ("PATH", path)));
 
         let Arguments {
             config,
@@ -36,6 +123,7 @@ impl<'a> Cli<'a> {
             debug,
             host,
             log_level,
+            ssh_wrapper_folder: _,
             tls,
             tlscacert,
             tlscert,
@@ -48,18 +136,11 @@ impl<'a> Cli<'a> {
             .args(context.iter().flat_map(|context| ["--context", context]))
             .args(debug.then_some("--debug").iter())
             .args(host.iter().flat_map(|host| ["--host", host]))
-            .args(log_level.iter().flat_map(|log_level| {
-                [
-                    "--log-level",
-                    match log_level {
-                        log::Level::Debug => "debug",
-                        log::Level::Info => "info",
-                        log::Level::Warn => "warn",
-                        log::Level::Error => "error",
-                        log::Level::Fatal => "fatal",
-                    },
-                ]
-            }))
+            .args(
+                log_level
+                    .iter()
+                    .flat_map(|log_level| ["--log-level", log_level_name(*log_level)]),
+            )
             .args(tls.then_some("--tls").iter())
             .args(
                 tlscacert
@@ -73,16 +154,216 @@ impl<'a> Cli<'a> {
         command
     }
 
+    pub fn arguments(&self) -> Arguments<'a> {
+        self.arguments
+    }
+
+    pub fn container_engine(&self) -> &'a str {
+        self.container_engine
+    }
+
+    // Detects the container engine and, if a Compose CLI is given, the Compose
+    // version, for features to fail early where unsupported.
+    pub fn probe(
+        &self,
+        docker_compose_cli: Option<&docker_compose::Cli>,
+    ) -> anyhow::Result<Engine> {
+        let mut engine = probe_engine(self.container_engine, |arguments| {
+            command::stdout_utf8(self.command().args(arguments))
+        })?;
+
+        if let Some(docker_compose_cli) = docker_compose_cli {
+            engine.compose_version = Some(
+                docker_compose_cli
+                    .version()
+                    .context("Unable to get Compose version")?,
+            );
+        }
+
+        log::debug!(
+            "Detected container engine {:?} of kind {:?}, version {:?}, \
+            Compose version {:?}.",
+            self.container_engine,
+            engine.kind,
+            engine.version,
+            engine.compose_version,
+        );
+        Ok(engine)
+    }
+
     pub fn default_daemon(&self) -> Self {
         Self {
-            arguments: Arguments {
-                context: None,
-                host: None,
-                ..self.arguments
-            },
+            arguments: self.arguments.default_daemon(),
             container_engine: self.container_engine,
         }
     }
+
+    pub fn with_host(&self, host: &'a str) -> Self {
+        Self {
+            arguments: self.arguments.with_host(host),
+            container_engine: self.container_engine,
+        }
+    }
+}
+
+// Detects the container engine with the given runner of its subcommands, which
+// may run them locally or on an SSH host, for example.
+pub fn probe_engine(
+    container_engine: &str,
+    run: impl Fn(&[&str]) -> anyhow::Result<String>,
+) -> anyhow::Result<Engine> {
+    let version = run(&["version", "--format", "json"])?;
+    let version = serde_json::from_str(&version).context("Unable to deserialize version")?;
+    let mut engine = Engine::new(container_engine, version);
+
+    if engine.kind == EngineKind::Podman {
+        let info = run(&["info", "--format", "json"])?;
+        let info = serde_json::from_str(&info).context("Unable to deserialize info")?;
+        engine.rootless = get_rootless(info);
+    }
+
+    Ok(engine)
+}
+
+pub fn log_level_name(log_level: log::Level) -> &'static str {
+    match log_level {
+        log::Level::Debug => "debug",
+        log::Level::Info => "info",
+        log::Level::Warn => "warn",
+        log::Level::Error => "error",
+        log::Level::Fatal => "fatal",
+    }
+}
+
+impl Engine {
+    fn new(container_engine: &str, version: Version) -> Self {
+        let Version { client, server } = version;
+        let VersionPart {
+            api_version,
+            components,
+            platform,
+            version,
+        } = server.or(client).unwrap_or_default();
+
+        let names = platform
+            .into_iter()
+            .chain(components.iter().cloned())
+            .map(|Named { name }| name.to_lowercase())
+            .collect::<Vec<_>>();
+        let kind = if names.iter().any(|name| name.contains("podman")) {
+            EngineKind::Podman
+        } else if names.iter().any(|name| name.contains("docker")) {
+            EngineKind::Docker
+        } else {
+            EngineKind::from_program(container_engine)
+        };
+        let rootless = match kind {
+            EngineKind::Docker => Some(names.iter().any(|name| name == "rootlesskit")),
+            EngineKind::Podman | EngineKind::Other => None,
+        };
+
+        Self {
+            api_version,
+            compose_version: None,
+            kind,
+            rootless,
+            version,
+        }
+    }
+}
+
+impl EngineKind {
+    pub fn from_program(container_engine: &str) -> Self {
+        match path::Path::new(container_engine)
+            .file_name()
+            .and_then(|name| name.to_str())
+        {
+            Some("docker") => Self::Docker,
+            Some("podman") => Self::Podman,
+            _ => Self::Other,
+        }
+    }
+}
+
+// Podman omits the algorithm prefix of image IDs in some places.
+pub fn normalize_image_id(image_id: &str) -> String {
+    image_id.strip_prefix("sha256:").unwrap_or(image_id).into()
+}
+
+// Podman tells rootless mode in its own format, whereas the Docker CLI connected
+// to Podman lists it among security options.
+fn get_rootless(
+    Info {
+        host,
+        security_options,
+    }: Info,
+) -> Option<bool> {
+    host.map(|host| host.security.rootless).or_else(|| {
+        security_options.map(|security_options| {
+            security_options.iter().any(|security_option| {
+                security_option
+                    .split(',')
+                    .any(|field| field == "name=rootless")
+            })
+        })
+    })
+}
+
+fn parse_current_context(config: &str) -> Option<String> {
+    serde_json::from_str::<ClientConfig>(config)
+        .ok()?
+        .current_context
+        .filter(|context| !context.is_empty())
+}
+
+// Client config file `config.json` of Docker.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClientConfig {
+    current_context: Option<String>,
+}
+
+// Output of `docker info --format json` or `podman info --format json`.
+#[derive(serde::Deserialize)]
+struct Info {
+    host: Option<InfoHost>,
+    #[serde(rename = "SecurityOptions")]
+    security_options: Option<Vec<String>>,
+}
+
+#[derive(serde::Deserialize)]
+struct InfoHost {
+    security: InfoSecurity,
+}
+
+#[derive(serde::Deserialize)]
+struct InfoSecurity {
+    rootless: bool,
+}
+
+// Output of `docker version --format json` or `podman version --format json`.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Version {
+    client: Option<VersionPart>,
+    server: Option<VersionPart>,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct VersionPart {
+    #[serde(alias = "APIVersion")]
+    api_version: Option<String>,
+    #[serde(default)]
+    components: Vec<Named>,
+    platform: Option<Named>,
+    version: Option<String>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Named {
+    name: String,
 }
 
 #[cfg(test)]
@@ -99,6 +380,7 @@ mod tests {
                 debug: false,
                 host: None,
                 log_level: None,
+                ssh_wrapper_folder: None,
                 tls: false,
                 tlscacert: None,
                 tlscert: None,
@@ -123,6 +405,7 @@ mod tests {
                 debug: true,
                 host: Some("host"),
                 log_level: Some(log::Level::Warn),
+                ssh_wrapper_folder: None,
                 tls: true,
                 tlscacert: Some("tlscacert"),
                 tlscert: Some("tlscert"),
@@ -168,6 +451,7 @@ mod tests {
                 debug: true,
                 host: Some("host"),
                 log_level: None,
+                ssh_wrapper_folder: None,
                 tls: false,
                 tlscacert: None,
                 tlscert: None,
@@ -182,4 +466,114 @@ mod tests {
         assert_eq!(command.get_args().collect::<Vec<_>>(), ["--debug"]);
         Ok(())
     }
+
+    #[test]
+    fn handles_with_host() -> anyhow::Result<()> {
+        let command = Cli::new(
+            "container-engine",
+            Arguments {
+                config: None,
+                context: Some("context"),
+                debug: false,
+                host: Some("host"),
+                log_level: None,
+                ssh_wrapper_folder: None,
+                tls: false,
+                tlscacert: None,
+                tlscert: None,
+                tlskey: None,
+                tlsverify: false,
+            },
+        )
+        .with_host("other-host")
+        .command();
+
+        assert_eq!(command.get_program(), "container-engine");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["--host", "other-host"],
+        );
+        Ok(())
+    }
+
+    #[test_case::test_case(
+        "docker",
+        r#"{"Client":{"Version":"28.0.1","ApiVersion":"1.48"},"Server":{"Platform":{"Name":"Docker Engine - Community"},"Components":[{"Name":"Engine"},{"Name":"containerd"}],"Version":"28.0.1","ApiVersion":"1.48"}}"#,
+        EngineKind::Docker,
+        Some(false),
+        Some("1.48");
+        "Docker"
+    )]
+    #[test_case::test_case(
+        "docker",
+        r#"{"Server":{"Platform":{"Name":"Docker Engine - Community"},"Components":[{"Name":"Engine"},{"Name":"rootlesskit"}],"Version":"28.0.1","ApiVersion":"1.48"}}"#,
+        EngineKind::Docker,
+        Some(true),
+        Some("1.48");
+        "Docker rootless"
+    )]
+    #[test_case::test_case(
+        "docker",
+        r#"{"Server":{"Platform":{"Name":""},"Components":[{"Name":"Podman Engine"}],"Version":"5.4.0","ApiVersion":"1.41"}}"#,
+        EngineKind::Podman,
+        None,
+        Some("1.41");
+        "Docker CLI with Podman"
+    )]
+    #[test_case::test_case(
+        "/usr/bin/podman",
+        r#"{"Client":{"APIVersion":"5.4.0","Version":"5.4.0"},"Server":null}"#,
+        EngineKind::Podman,
+        None,
+        Some("5.4.0");
+        "Podman"
+    )]
+    #[test_case::test_case(
+        "nerdctl",
+        r#"{"Client":{"Version":"v2.0.3"}}"#,
+        EngineKind::Other,
+        None,
+        None;
+        "other"
+    )]
+    fn engine_handles(
+        container_engine: &str,
+        version: &str,
+        expected_kind: EngineKind,
+        expected_rootless: Option<bool>,
+        expected_api_version: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let engine = Engine::new(container_engine, serde_json::from_str(version)?);
+
+        assert_eq!(engine.kind, expected_kind);
+        assert_eq!(engine.rootless, expected_rootless);
+        assert_eq!(engine.api_version.as_deref(), expected_api_version);
+        Ok(())
+    }
+
+    #[test_case::test_case(r#"{"host":{"security":{"rootless":true}}}"#, Some(true); "Podman rootless")]
+    #[test_case::test_case(r#"{"host":{"security":{"rootless":false}}}"#, Some(false); "Podman rootful")]
+    #[test_case::test_case(
+        r#"{"SecurityOptions":["name=seccomp,profile=builtin","name=rootless"]}"#,
+        Some(true);
+        "Docker CLI rootless"
+    )]
+    #[test_case::test_case(
+        r#"{"SecurityOptions":["name=seccomp,profile=builtin"]}"#,
+        Some(false);
+        "Docker CLI rootful"
+    )]
+    #[test_case::test_case("{}", None; "unknown")]
+    fn gets_rootless(info: &str, expected: Option<bool>) -> anyhow::Result<()> {
+        assert_eq!(get_rootless(serde_json::from_str(info)?), expected);
+        Ok(())
+    }
+
+    #[test_case::test_case(r#"{"currentContext":"remote"}"#, Some("remote"); "set")]
+    #[test_case::test_case(r#"{"currentContext":""}"#, None; "empty")]
+    #[test_case::test_case(r#"{"auths":{}}"#, None; "unset")]
+    #[test_case::test_case("", None; "invalid")]
+    fn parses_current_context(config: &str, expected: Option<&str>) {
+        assert_eq!(parse_current_context(config).as_deref(), expected);
+    }
 }
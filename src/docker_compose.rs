@@ -1,11 +1,16 @@
+use super::command;
 use super::docker;
+use super::log;
 use std::process;
 
+#[derive(Clone, Copy)]
 pub struct Cli<'a> {
     arguments: Arguments<'a>,
-    docker_cli: docker::Cli<'a>,
+    compose_command: &'a [String],
+    docker_arguments: docker::Arguments<'a>,
 }
 
+#[derive(Clone, Copy, Default)]
 pub struct Arguments<'a> {
     pub all_resources: bool,
     pub ansi: Option<&'a str>,
@@ -21,17 +26,27 @@ pub struct Arguments<'a> {
 
 impl<'a> Cli<'a> {
     pub fn new(
+        compose_command: &'a [String],
         docker_arguments: docker::Arguments<'a>,
         docker_compose_arguments: Arguments<'a>,
     ) -> Self {
         Self {
             arguments: docker_compose_arguments,
-            docker_cli: docker::Cli::new("docker", docker_arguments),
+            compose_command,
+            docker_arguments,
         }
     }
 
     pub fn command(&self) -> process::Command {
-        let mut command = self.docker_cli.command();
+        let mut command = match self.compose_command {
+            [container_engine, subcommand] if subcommand == "compose" => {
+                let mut command =
+                    docker::Cli::new(container_engine, self.docker_arguments).command();
+                command.arg(subcommand);
+                command
+            }
+            _ => standalone_command(self.compose_command, &self.docker_arguments),
+        };
 
         let Arguments {
             all_resources,
@@ -47,7 +62,6 @@ impl<'a> Cli<'a> {
         } = &self.arguments;
 
         command
-            .arg("compose")
             .args(all_resources.then_some("--all-resources").iter())
             .args(ansi.iter().flat_map(|ansi| ["--ansi", ansi]))
             .args(compatibility.then_some("--compatibility").iter())
@@ -82,21 +96,189 @@ impl<'a> Cli<'a> {
 
         command
     }
+
+    pub fn default_daemon(&self) -> Self {
+        Self {
+            arguments: self.arguments,
+            compose_command: self.compose_command,
+            docker_arguments: self.docker_arguments.default_daemon(),
+        }
+    }
+
+    pub fn with_host(&self, host: &'a str) -> Self {
+        Self {
+            arguments: self.arguments,
+            compose_command: self.compose_command,
+            docker_arguments: self.docker_arguments.with_host(host),
+        }
+    }
+
+    pub fn version(&self) -> anyhow::Result<String> {
+        let version = command::stdout_utf8(self.command().args(["version", "--short"]))?;
+        Ok(version.trim().into())
+    }
+}
+
+pub fn check_version(version: &str) -> anyhow::Result<()> {
+    match major_version(version) {
+        Some(major_version) if major_version < 2 => Err(anyhow::anyhow!(
+            "Compose version {version} is unsupported, \
+            need version 2 or later for `config --format json`"
+        )),
+        Some(_) => Ok(()),
+        None => {
+            log::debug!("Unable to parse Compose version {version:?}, continuing.");
+            Ok(())
+        }
+    }
+}
+
+fn major_version(version: &str) -> Option<u32> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .next()
+        .and_then(|major| major.parse().ok())
+}
+
+pub fn resolve_command(
+    container_engine: &str,
+    compose_command: Vec<String>,
+) -> anyhow::Result<Vec<String>> {
+    resolve_command_via(container_engine, compose_command, |candidate| {
+        candidate.split_first().is_some_and(|(program, arguments)| {
+            command::is_success(
+                process::Command::new(program)
+                    .args(arguments)
+                    .arg("version"),
+            )
+        })
+    })
+}
+
+// Like `resolve_command` but checks candidates with given function, say, on an
+// SSH host.
+pub fn resolve_command_via(
+    container_engine: &str,
+    compose_command: Vec<String>,
+    mut is_available: impl FnMut(&[String]) -> bool,
+) -> anyhow::Result<Vec<String>> {
+    if !compose_command.is_empty() {
+        log::debug!("Using given Compose command: {compose_command:?}");
+        return Ok(compose_command);
+    }
+
+    let mut candidates = vec![
+        vec![container_engine.into(), "compose".into()],
+        vec!["docker".into(), "compose".into()],
+        vec!["docker-compose".into()],
+    ];
+    candidates.dedup();
+
+    for candidate in &candidates {
+        if is_available(candidate) {
+            log::debug!("Using detected Compose command: {candidate:?}");
+            return Ok(candidate.clone());
+        }
+    }
+
+    let candidates = candidates
+        .iter()
+        .map(|candidate| format!("`{}`", candidate.join(" ")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(anyhow::anyhow!(
+        "Unable to find Compose command, tried {candidates}; \
+        use `--compose-command` to pass one explicitly"
+    ))
+}
+
+// Standalone Compose does not take Docker arguments in general. It takes the
+// TLS and logging flags of Compose v1, which Compose v2 translates to Docker
+// arguments, whereas the remaining arguments are passed as environment
+// variables.
+fn standalone_command(
+    compose_command: &[String],
+    docker_arguments: &docker::Arguments,
+) -> process::Command {
+    let (program, arguments) = compose_command
+        .split_first()
+        .map_or(("docker-compose", &[][..]), |(program, arguments)| {
+            (program.as_str(), arguments)
+        });
+
+    let mut command = process::Command::new(program);
+    command
+        .args(arguments)
+        .envs(docker_arguments.ssh_path().map(|path| ("PATH", path)));
+
+    let docker::Arguments {
+        config,
+        context,
+        debug,
+        host,
+        log_level,
+        ssh_wrapper_folder: _,
+        tls,
+        tlscacert,
+        tlscert,
+        tlskey,
+        tlsverify,
+    } = docker_arguments;
+
+    for (key, value) in [
+        ("DOCKER_CONFIG", *config),
+        ("DOCKER_CONTEXT", *context),
+        ("DOCKER_HOST", *host),
+    ] {
+        if let Some(value) = value {
+            command.env(key, value);
+        }
+    }
+
+    command
+        .args(debug.then_some("--verbose").iter())
+        .args(
+            log_level
+                .iter()
+                .flat_map(|log_level| ["--log-level", docker::log_level_name(*log_level)]),
+        )
+        .args(tls.then_some("--tls").iter())
+        .args(
+            tlscacert
+                .iter()
+                .flat_map(|tlscacert| ["--tlscacert", tlscacert]),
+        )
+        .args(tlscert.iter().flat_map(|tlscert| ["--tlscert", tlscert]))
+        .args(tlskey.iter().flat_map(|tlskey| ["--tlskey", tlskey]))
+        .args(tlsverify.then_some("--tlsverify").iter());
+
+    command
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test_case::test_case("2.24.5", Some(2); "plain")]
+    #[test_case::test_case("v2.33.1-desktop.1", Some(2); "prefixed")]
+    #[test_case::test_case("1.29.2", Some(1); "v1")]
+    #[test_case::test_case("unknown", None; "invalid")]
+    fn major_version_handles(version: &str, expected: Option<u32>) {
+        assert_eq!(major_version(version), expected)
+    }
+
     #[test]
     fn handles_minimum() -> anyhow::Result<()> {
         let command = Cli::new(
+            &["docker".into(), "compose".into()],
             docker::Arguments {
                 config: None,
                 context: None,
                 debug: true,
                 host: None,
                 log_level: None,
+                ssh_wrapper_folder: None,
                 tls: false,
                 tlscacert: None,
                 tlscert: None,
@@ -129,12 +311,14 @@ mod tests {
     #[test]
     fn handles_maximum() -> anyhow::Result<()> {
         let command = Cli::new(
+            &["podman".into(), "compose".into()],
             docker::Arguments {
                 config: None,
                 context: None,
                 debug: true,
                 host: None,
                 log_level: None,
+                ssh_wrapper_folder: None,
                 tls: false,
                 tlscacert: None,
                 tlscert: None,
@@ -156,7 +340,7 @@ mod tests {
         )
         .command();
 
-        assert_eq!(command.get_program(), "docker");
+        assert_eq!(command.get_program(), "podman");
         assert_eq!(
             command.get_args().collect::<Vec<_>>(),
             [
@@ -184,4 +368,90 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn handles_standalone() -> anyhow::Result<()> {
+        let command = Cli::new(
+            &["docker-compose".into()],
+            docker::Arguments {
+                config: None,
+                context: Some("context"),
+                debug: true,
+                host: Some("host"),
+                log_level: Some(log::Level::Warn),
+                ssh_wrapper_folder: None,
+                tls: true,
+                tlscacert: Some("tlscacert"),
+                tlscert: Some("tlscert"),
+                tlskey: Some("tlskey"),
+                tlsverify: true,
+            },
+            Arguments {
+                all_resources: false,
+                ansi: None,
+                compatibility: false,
+                env_file: &[],
+                file: &["file".into()],
+                parallel: None,
+                profile: &[],
+                progress: None,
+                project_directory: None,
+                project_name: None,
+            },
+        )
+        .command();
+
+        assert_eq!(command.get_program(), "docker-compose");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "--verbose",
+                "--log-level",
+                "warn",
+                "--tls",
+                "--tlscacert",
+                "tlscacert",
+                "--tlscert",
+                "tlscert",
+                "--tlskey",
+                "tlskey",
+                "--tlsverify",
+                "--file",
+                "file",
+            ],
+        );
+        assert_eq!(
+            command.get_envs().collect::<Vec<_>>(),
+            [
+                ("DOCKER_CONTEXT".as_ref(), Some("context".as_ref())),
+                ("DOCKER_HOST".as_ref(), Some("host".as_ref())),
+            ],
+        );
+        Ok(())
+    }
+
+    #[test_case::test_case(&[], &["podman", "compose"]; "engine plugin")]
+    #[test_case::test_case(&["podman"], &["docker", "compose"]; "Docker plugin")]
+    #[test_case::test_case(&["podman", "docker"], &["docker-compose"]; "standalone")]
+    fn resolves_command(unavailable: &[&str], expected: &[&str]) -> anyhow::Result<()> {
+        let compose_command = resolve_command_via("podman", vec![], |candidate| {
+            !unavailable.contains(&candidate[0].as_str())
+        })?;
+
+        assert_eq!(compose_command, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_given_command() -> anyhow::Result<()> {
+        let compose_command = resolve_command_via("docker", vec!["my-compose".into()], |_| false)?;
+
+        assert_eq!(compose_command, ["my-compose"]);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_missing_command() {
+        assert!(resolve_command_via("docker", vec![], |_| false).is_err())
+    }
 }
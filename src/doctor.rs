@@ -0,0 +1,391 @@
+use super::command;
+use super::docker;
+use super::docker_compose;
+use super::ssh;
+use super::tunnel_ssh;
+
+pub fn go(
+    In {
+        compose_command,
+        docker_cli,
+        host,
+    }: In,
+) -> anyhow::Result<()> {
+    let mut report = Report::default();
+
+    match host {
+        None => {
+            check_engine(&mut report, &docker_cli);
+            check_compose(&mut report, compose_command, &docker_cli);
+        }
+        Some(Host { ssh_cli, ssh_host }) => check_host(
+            &mut report,
+            compose_command,
+            docker_cli.container_engine(),
+            &ssh_cli,
+            &ssh_host,
+        ),
+    }
+
+    report.finish()
+}
+
+pub struct In<'a> {
+    pub compose_command: Vec<String>,
+    pub docker_cli: docker::Cli<'a>,
+    pub host: Option<Host<'a>>,
+}
+
+pub struct Host<'a> {
+    pub ssh_cli: ssh::Cli<'a>,
+    pub ssh_host: String,
+}
+
+#[derive(Default)]
+struct Report {
+    check_count: usize,
+    failure_count: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, result: anyhow::Result<String>, hint: &str) -> bool {
+        self.check_count += 1;
+        print!("{}", render_check(name, &result, hint));
+
+        let is_passed = result.is_ok();
+        if !is_passed {
+            self.failure_count += 1;
+        }
+        is_passed
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        let Self {
+            check_count,
+            failure_count,
+        } = self;
+
+        if failure_count == 0 {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "{failure_count} of {check_count} checks failed"
+            ))
+        }
+    }
+}
+
+fn render_check(name: &str, result: &anyhow::Result<String>, hint: &str) -> String {
+    match result {
+        Ok(detail) => format!("[pass] {name}: {detail}\n"),
+        Err(error) => format!("[fail] {name}: {error:#}\n       Hint: {hint}\n"),
+    }
+}
+
+fn check_engine(report: &mut Report, docker_cli: &docker::Cli) {
+    let container_engine = docker_cli.container_engine();
+
+    report.check(
+        "Container engine",
+        docker_cli
+            .probe(None)
+            .map(|engine| describe_engine(container_engine, &engine)),
+        "Check that the container engine is installed and its daemon is \
+        reachable, for example with `--host`",
+    );
+}
+
+fn check_compose(report: &mut Report, compose_command: Vec<String>, docker_cli: &docker::Cli) {
+    let compose_command =
+        docker_compose::resolve_command(docker_cli.container_engine(), compose_command);
+    let result = compose_command.and_then(|compose_command| {
+        let version = docker_compose::Cli::new(
+            &compose_command,
+            docker_cli.arguments(),
+            docker_compose::Arguments::default(),
+        )
+        .version()?;
+        Ok(describe_compose(&compose_command, &version))
+    });
+
+    report.check(
+        "Compose",
+        result,
+        "Install the Docker Compose plugin or pass `--compose-command`",
+    );
+}
+
+// Checks what `provision` sets up, running everything on the host via SSH, so
+// no local daemon is needed.
+fn check_host(
+    report: &mut Report,
+    compose_command: Vec<String>,
+    container_engine: &str,
+    ssh_cli: &ssh::Cli,
+    ssh_host: &str,
+) {
+    let ssh = |arguments: &[&str]| {
+        let mut command = ssh_cli.command();
+        command.arg(ssh_host).args(arguments);
+        command
+    };
+
+    let is_connected = report.check(
+        "SSH connection",
+        command::status_ok(&mut ssh(&["true"])).map(|()| format!("{ssh_host:?} is reachable")),
+        "Check the host reference and SSH config, for example with \
+        `--ssh-config`",
+    );
+    if !is_connected {
+        return;
+    }
+
+    let provision_hint =
+        format!("Run `kerek --container-engine {container_engine} provision {ssh_host}`");
+
+    let engine = docker::probe_engine(container_engine, |arguments| {
+        command::stdout_utf8(&mut ssh(&[&[container_engine], arguments].concat()))
+    });
+    let engine = match engine {
+        Ok(engine) => {
+            report.check(
+                "Container engine on host",
+                Ok(describe_engine(container_engine, &engine)),
+                &provision_hint,
+            );
+            Some(engine)
+        }
+        Err(error) => {
+            report.check("Container engine on host", Err(error), &provision_hint);
+            None
+        }
+    };
+    let engine_kind = engine.as_ref().map_or_else(
+        || docker::EngineKind::from_program(container_engine),
+        |engine| engine.kind,
+    );
+    let rootless = engine.and_then(|engine| engine.rootless);
+
+    // Detects Compose like deploying does, but on the host.
+    let ssh_compose = |compose_command: &[String], arguments: &[&str]| {
+        ssh(&compose_command
+            .iter()
+            .map(|argument| argument.as_str())
+            .chain(arguments.iter().copied())
+            .collect::<Vec<_>>())
+    };
+    report.check(
+        "Compose on host",
+        docker_compose::resolve_command_via(container_engine, compose_command, |candidate| {
+            command::is_success(&mut ssh_compose(candidate, &["version"]))
+        })
+        .and_then(|compose_command| {
+            let version =
+                command::stdout_utf8(&mut ssh_compose(&compose_command, &["version", "--short"]))?;
+            Ok(describe_compose(&compose_command, &version))
+        }),
+        "Install the Docker Compose plugin on host or pass `--compose-command`",
+    );
+
+    report.check(
+        "Remote socket",
+        tunnel_ssh::infer_remote_socket(tunnel_ssh::RemoteConfig {
+            container_engine,
+            ssh_cli,
+            ssh_host,
+        })
+        .and_then(|socket| {
+            command::status_ok(&mut ssh(&["test", "-S", &socket]))?;
+            Ok(format!("{socket:?} exists"))
+        }),
+        &match engine_kind {
+            docker::EngineKind::Podman => {
+                "Run `systemctl --user enable --now podman.socket` on host".into()
+            }
+            docker::EngineKind::Docker => "Run `sudo systemctl enable --now docker` on host".into(),
+            docker::EngineKind::Other => provision_hint.clone(),
+        },
+    );
+
+    match engine_kind {
+        docker::EngineKind::Docker => {
+            let result = command::stdout_utf8(&mut ssh(&["id", "-u"])).and_then(|user_id| {
+                let groups = command::stdout_utf8(&mut ssh(&["id", "-nG"]))?;
+                Ok(check_docker_group(&user_id, &groups, rootless))
+            });
+            if let Some(result) = result.unwrap_or_else(|error| Some(Err(error))) {
+                report.check(
+                    "Docker group membership",
+                    result,
+                    "Run `sudo usermod --append --groups docker \"${USER}\"` on \
+                    host, then reconnect",
+                );
+            }
+        }
+        // Rootful Podman runs as a system service, which needs no linger.
+        docker::EngineKind::Podman if rootless == Some(true) => {
+            report.check(
+                "Linger for rootless Podman",
+                command::stdout_utf8(&mut ssh(&[
+                    "loginctl",
+                    "show-user",
+                    "--property",
+                    "Linger",
+                    "--value",
+                    "\"${USER}\"",
+                ]))
+                .and_then(|linger| check_linger(&linger)),
+                "Run `sudo loginctl enable-linger \"${USER}\"` on host",
+            );
+        }
+        docker::EngineKind::Podman | docker::EngineKind::Other => {}
+    }
+}
+
+fn describe_engine(
+    container_engine: &str,
+    docker::Engine {
+        api_version,
+        kind,
+        rootless,
+        version,
+        ..
+    }: &docker::Engine,
+) -> String {
+    let version = show_optional(version.as_deref());
+    let api_version = show_optional(api_version.as_deref());
+    let rootless =
+        show_optional(rootless.map(|rootless| if rootless { "rootless" } else { "rootful" }));
+    format!(
+        "{container_engine:?} is {kind:?} version {version}, \
+        API version {api_version}, {rootless}"
+    )
+}
+
+fn describe_compose(compose_command: &[String], version: &str) -> String {
+    let compose_command = compose_command.join(" ");
+    let version = version.trim();
+    format!("`{compose_command}` is version {version}")
+}
+
+// Group membership is moot for root as well as for rootless Docker, whose
+// socket belongs to the user. Returns `None` if the check does not apply.
+fn check_docker_group(
+    user_id: &str,
+    groups: &str,
+    rootless: Option<bool>,
+) -> Option<anyhow::Result<String>> {
+    if user_id.trim() == "0" || rootless == Some(true) {
+        None
+    } else if groups.split_whitespace().any(|group| group == "docker") {
+        Some(Ok("SSH user is in group \"docker\"".into()))
+    } else {
+        Some(Err(anyhow::anyhow!("SSH user is not in group \"docker\"")))
+    }
+}
+
+fn check_linger(linger: &str) -> anyhow::Result<String> {
+    if linger.trim() == "yes" {
+        Ok("enabled for SSH user".into())
+    } else {
+        Err(anyhow::anyhow!("not enabled for SSH user"))
+    }
+}
+
+fn show_optional<T: Into<String>>(value: Option<T>) -> String {
+    value.map_or_else(|| "unknown".into(), |value| value.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_passed_check() {
+        assert_eq!(
+            render_check("Name", &Ok("detail".into()), "hint"),
+            "[pass] Name: detail\n",
+        )
+    }
+
+    #[test]
+    fn renders_failed_check() {
+        let error = anyhow::anyhow!("cause").context("error");
+
+        assert_eq!(
+            render_check("Name", &Err(error), "hint"),
+            "[fail] Name: error: cause\n       Hint: hint\n",
+        )
+    }
+
+    #[test_case::test_case(&[true, true], None; "all passed")]
+    #[test_case::test_case(&[true, false, false], Some("2 of 3 checks failed"); "some failed")]
+    fn finishes_report(results: &[bool], expected_error: Option<&str>) {
+        let mut report = Report::default();
+        for &is_ok in results {
+            let result = if is_ok {
+                Ok("detail".into())
+            } else {
+                Err(anyhow::anyhow!("error"))
+            };
+            assert_eq!(report.check("Name", result, "hint"), is_ok);
+        }
+
+        assert_eq!(
+            report.finish().err().map(|error| error.to_string()),
+            expected_error.map(|error| error.into()),
+        )
+    }
+
+    #[test_case::test_case(
+        Some(false),
+        "\"docker\" is Docker version 28.0.1, API version 1.48, rootful";
+        "rootful"
+    )]
+    #[test_case::test_case(
+        None,
+        "\"docker\" is Docker version 28.0.1, API version 1.48, unknown";
+        "unknown"
+    )]
+    fn describes_engine(rootless: Option<bool>, expected: &str) {
+        let engine = docker::Engine {
+            api_version: Some("1.48".into()),
+            compose_version: None,
+            kind: docker::EngineKind::Docker,
+            rootless,
+            version: Some("28.0.1".into()),
+        };
+
+        assert_eq!(describe_engine("docker", &engine), expected)
+    }
+
+    #[test]
+    fn describes_compose() {
+        assert_eq!(
+            describe_compose(&["docker".into(), "compose".into()], "2.39.1\n"),
+            "`docker compose` is version 2.39.1",
+        )
+    }
+
+    #[test_case::test_case("1000", "user docker", None, Some(true); "member")]
+    #[test_case::test_case("1000", "user dockerish", None, Some(false); "non-member")]
+    #[test_case::test_case("0\n", "root", None, None; "root")]
+    #[test_case::test_case("1000", "user", Some(true), None; "rootless")]
+    fn checks_docker_group(
+        user_id: &str,
+        groups: &str,
+        rootless: Option<bool>,
+        expected: Option<bool>,
+    ) {
+        assert_eq!(
+            check_docker_group(user_id, groups, rootless).map(|result| result.is_ok()),
+            expected,
+        )
+    }
+
+    #[test_case::test_case("yes\n", true; "enabled")]
+    #[test_case::test_case("no\n", false; "disabled")]
+    fn checks_linger(linger: &str, expected: bool) {
+        assert_eq!(check_linger(linger).is_ok(), expected)
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Context;
+use std::fs;
+
+pub fn read(path: &str) -> anyhow::Result<Inventory> {
+    let inventory = fs::read_to_string(path)
+        .with_context(|| format!("Unable to read inventory file {path:?}"))?;
+    parse(&inventory).with_context(|| format!("Unable to parse inventory file {path:?}"))
+}
+
+#[derive(Debug, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Inventory {
+    pub hosts: Vec<String>,
+    pub max_unavailable_hosts: Option<u16>,
+}
+
+// An empty list of hosts would otherwise mean deploying to the default daemon.
+fn parse(inventory: &str) -> anyhow::Result<Inventory> {
+    let inventory = toml::from_str::<Inventory>(inventory)?;
+    anyhow::ensure!(!inventory.hosts.is_empty(), "Inventory lists no hosts");
+    Ok(inventory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles() -> anyhow::Result<()> {
+        let inventory = parse(
+            r#"
+hosts = ["ssh://192.0.2.1", "ssh://192.0.2.2"]
+max-unavailable-hosts = 1
+"#,
+        )?;
+
+        assert_eq!(
+            inventory,
+            Inventory {
+                hosts: vec!["ssh://192.0.2.1".into(), "ssh://192.0.2.2".into()],
+                max_unavailable_hosts: Some(1),
+            },
+        );
+        Ok(())
+    }
+
+    #[test_case::test_case("hosts = []"; "empty")]
+    #[test_case::test_case("max-unavailable-hosts = 1"; "missing")]
+    fn rejects_no_hosts(inventory: &str) {
+        assert!(parse(inventory).is_err())
+    }
+}
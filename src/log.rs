@@ -39,6 +39,15 @@ macro_rules! info {
     }};
 }
 
+#[macro_export]
+macro_rules! warning {
+    ($($argument:tt)*) => {{
+        if $crate::log::level() <= $crate::log::Level::Warn {
+            eprintln!($($argument)*);
+        }
+    }};
+}
+
 #[macro_export]
 macro_rules! error {
     ($($argument:tt)*) => {{
@@ -51,3 +60,4 @@ macro_rules! error {
 pub use debug;
 pub use error;
 pub use info;
+pub use warning;
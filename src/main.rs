@@ -1,18 +1,31 @@
+mod bundle;
 mod command;
 mod deploy;
+mod diff;
 mod docker;
 mod docker_cli_plugin_metadata;
 mod docker_compose;
+mod doctor;
+mod inventory;
 mod log;
+mod progress;
 mod provision;
+mod prune;
 mod ssh;
+mod status;
+mod tar;
+mod temp;
 mod transfer_images;
 mod tunnel_ssh;
 
 use clap::Parser;
+use std::net;
+use std::path;
+use std::process;
 
 fn main() -> anyhow::Result<()> {
     let Cli {
+        compose_command,
         container_engine,
         docker_arguments,
         dry_run,
@@ -25,7 +38,75 @@ fn main() -> anyhow::Result<()> {
         docker_arguments.log_level.unwrap_or(log::Level::Info)
     })?;
 
+    if docker_arguments.host.len() > 1
+        && !matches!(
+            subcommand,
+            Subcommand::Deploy { .. } | Subcommand::TransferImages { .. }
+        )
+    {
+        return Err(anyhow::anyhow!(
+            "Multiple hosts are only supported by subcommands `deploy` and \
+            `transfer-images`"
+        ));
+    }
+
+    let multiplexing = if uses_ssh(&subcommand, &docker_arguments) {
+        ssh::Multiplexing::start()?
+    } else {
+        None
+    };
+    let control_folder = multiplexing.as_ref().map(ssh::Multiplexing::folder);
+    let docker_cli_arguments = docker::Arguments {
+        ssh_wrapper_folder: control_folder,
+        ..docker::Arguments::from(&docker_arguments)
+    };
+
     match subcommand {
+        Subcommand::Bundle {
+            subcommand:
+                BundleSubcommand::Create {
+                    docker_compose_arguments,
+                    out,
+                    service_names,
+                },
+        } => {
+            let compose_command =
+                docker_compose::resolve_command(&container_engine, compose_command)?;
+
+            bundle::create::go(bundle::create::In {
+                docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                docker_compose_cli: docker_compose::Cli::new(
+                    &compose_command,
+                    docker_cli_arguments,
+                    (&docker_compose_arguments).into(),
+                ),
+                dry_run,
+                out,
+                service_names,
+            })
+        }
+
+        Subcommand::Bundle {
+            subcommand:
+                BundleSubcommand::Load {
+                    bundle,
+                    deploy,
+                    docker_compose_arguments,
+                },
+        } => bundle::load::go(bundle::load::In {
+            bundle,
+            compose_command: if deploy {
+                docker_compose::resolve_command(&container_engine, compose_command)?
+            } else {
+                vec![]
+            },
+            deploy,
+            docker_arguments: docker_cli_arguments,
+            docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+            docker_compose_arguments: (&docker_compose_arguments).into(),
+            dry_run,
+        }),
+
         Subcommand::Deploy {
             docker_compose_arguments,
             docker_compose_up_arguments:
@@ -43,28 +124,129 @@ fn main() -> anyhow::Result<()> {
                     wait_timeout,
                     wait,
                 },
+            hosts_file,
+            keep_revisions,
+            max_unavailable_hosts,
+            prune_images,
             service_names,
-        } => deploy::go(deploy::In {
-            build,
-            docker_cli: docker::Cli::new(&container_engine, (&docker_arguments).into()),
-            docker_compose_cli: docker_compose::Cli::new(
-                (&docker_arguments).into(),
-                (&docker_compose_arguments).into(),
-            ),
-            dry_run,
-            force_recreate,
-            no_build,
-            no_deps,
-            no_start,
-            pull,
-            quiet_pull,
-            remove_orphans,
-            renew_anon_volumes,
-            service_names: service_names.into_iter().collect(),
-            timeout: timeout.map(|timeout| timeout.to_string()),
-            wait,
-            wait_timeout: wait_timeout.map(|wait_timeout| wait_timeout.to_string()),
-        }),
+            ssh_arguments,
+            transfer_images,
+        } => {
+            let compose_command =
+                docker_compose::resolve_command(&container_engine, compose_command)?;
+            let (hosts, max_unavailable_hosts) = match hosts_file {
+                None => (
+                    if docker_arguments.host.len() > 1 {
+                        docker_arguments.host.clone()
+                    } else {
+                        vec![]
+                    },
+                    max_unavailable_hosts,
+                ),
+                Some(hosts_file) => {
+                    if !docker_arguments.host.is_empty() {
+                        return Err(anyhow::anyhow!(
+                            "Options `--host` and `--hosts-file` are mutually exclusive"
+                        ));
+                    }
+                    let inventory = inventory::read(&hosts_file)?;
+                    (
+                        inventory.hosts,
+                        max_unavailable_hosts.or(inventory.max_unavailable_hosts),
+                    )
+                }
+            };
+            check_docker_ssh_arguments(
+                &container_engine,
+                if hosts.is_empty() {
+                    docker_cli_arguments.may_use_ssh()
+                } else {
+                    hosts.iter().any(|host| host.starts_with("ssh://"))
+                },
+                &ssh_arguments,
+            )?;
+
+            with_ssh_cli(
+                &docker_arguments,
+                &ssh_arguments,
+                control_folder,
+                |ssh_cli| {
+                    if let Some(multiplexing) = &multiplexing {
+                        multiplexing.apply_to_docker(&ssh_cli)?;
+                    }
+
+                    deploy::go(deploy::In {
+                        build,
+                        docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                        docker_compose_cli: docker_compose::Cli::new(
+                            &compose_command,
+                            docker_cli_arguments,
+                            (&docker_compose_arguments).into(),
+                        ),
+                        dry_run,
+                        force_recreate,
+                        hosts,
+                        max_unavailable_hosts: max_unavailable_hosts.unwrap_or(1),
+                        no_build,
+                        no_deps,
+                        no_start,
+                        prune_images: prune_images.then_some(keep_revisions),
+                        pull,
+                        quiet_pull,
+                        remove_orphans,
+                        renew_anon_volumes,
+                        service_names: service_names.into_iter().collect(),
+                        timeout: timeout.map(|timeout| timeout.to_string()),
+                        transfer_images,
+                        wait,
+                        wait_timeout: wait_timeout.map(|wait_timeout| wait_timeout.to_string()),
+                    })
+                },
+            )
+        }
+
+        Subcommand::Diff {
+            docker_compose_arguments,
+            exit_code,
+            format,
+            service_names,
+        } => {
+            let compose_command =
+                docker_compose::resolve_command(&container_engine, compose_command)?;
+
+            let has_drift = diff::go(diff::In {
+                docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                docker_compose_cli: docker_compose::Cli::new(
+                    &compose_command,
+                    docker_cli_arguments,
+                    (&docker_compose_arguments).into(),
+                ),
+                format,
+                service_names: service_names.into_iter().collect(),
+            })?;
+
+            if exit_code && has_drift {
+                drop(multiplexing);
+                process::exit(diff::DRIFT_EXIT_CODE)
+            }
+            Ok(())
+        }
+
+        Subcommand::Doctor {
+            ssh_arguments,
+            ssh_host,
+        } => with_ssh_cli(
+            &docker_arguments,
+            &ssh_arguments,
+            control_folder,
+            |ssh_cli| {
+                doctor::go(doctor::In {
+                    compose_command,
+                    docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                    host: ssh_host.map(|ssh_host| doctor::Host { ssh_cli, ssh_host }),
+                })
+            },
+        ),
 
         Subcommand::DockerCliPluginMetadata => {
             let metadata = docker_cli_plugin_metadata::go()?;
@@ -72,44 +254,158 @@ fn main() -> anyhow::Result<()> {
             Ok(())
         }
 
+        Subcommand::Prune {
+            docker_compose_arguments,
+            keep_revisions,
+        } => {
+            let compose_command =
+                docker_compose::resolve_command(&container_engine, compose_command)?;
+
+            prune::go(prune::In {
+                docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                docker_compose_cli: docker_compose::Cli::new(
+                    &compose_command,
+                    docker_cli_arguments,
+                    (&docker_compose_arguments).into(),
+                ),
+                dry_run,
+                keep_revisions,
+            })
+        }
+
         Subcommand::Provision {
             force,
             host,
             ssh_arguments,
-        } => provision::go(provision::In {
-            container_engine,
-            dry_run,
-            force,
-            has_ssh_config_override: ssh_arguments.ssh_config.is_some(),
-            host,
-            ssh_cli: ssh_cli(&docker_arguments, &ssh_arguments),
-        }),
+        } => with_ssh_cli(
+            &docker_arguments,
+            &ssh_arguments,
+            control_folder,
+            |ssh_cli| {
+                provision::go(provision::In {
+                    container_engine,
+                    dry_run,
+                    force,
+                    has_ssh_override: ssh_arguments.is_set(),
+                    host,
+                    ssh_cli,
+                })
+            },
+        ),
+
+        Subcommand::Status {
+            docker_compose_arguments,
+            service_names,
+        } => {
+            let compose_command =
+                docker_compose::resolve_command(&container_engine, compose_command)?;
+
+            status::go(status::In {
+                docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                docker_compose_cli: docker_compose::Cli::new(
+                    &compose_command,
+                    docker_cli_arguments,
+                    (&docker_compose_arguments).into(),
+                ),
+                service_names: service_names.into_iter().collect(),
+            })
+        }
 
         Subcommand::TransferImages {
+            compose,
             compress,
+            delta,
+            direct,
+            docker_compose_arguments,
             force,
             images,
-        } => transfer_images::go(transfer_images::In {
-            compress,
-            docker_cli: docker::Cli::new(&container_engine, (&docker_arguments).into()),
-            dry_run,
-            force,
-            images,
-        }),
+            resumable,
+            source_host,
+            ssh_arguments,
+        } => {
+            let compose_command = if compose {
+                docker_compose::resolve_command(&container_engine, compose_command)?
+            } else {
+                vec![]
+            };
+            check_docker_ssh_arguments(
+                &container_engine,
+                source_host
+                    .iter()
+                    .chain(&docker_arguments.host)
+                    .any(|host| host.starts_with("ssh://"))
+                    || (docker_arguments.host.is_empty() && docker_cli_arguments.may_use_ssh()),
+                &ssh_arguments,
+            )?;
+            with_ssh_cli(
+                &docker_arguments,
+                &ssh_arguments,
+                control_folder,
+                |ssh_cli| {
+                    if let Some(multiplexing) = &multiplexing {
+                        multiplexing.apply_to_docker(&ssh_cli)?;
+                    }
+
+                    transfer_images::go(transfer_images::In {
+                        compress,
+                        delta,
+                        direct,
+                        docker_cli: docker::Cli::new(&container_engine, docker_cli_arguments),
+                        docker_compose_cli: compose.then(|| {
+                            docker_compose::Cli::new(
+                                &compose_command,
+                                docker_cli_arguments,
+                                (&docker_compose_arguments).into(),
+                            )
+                        }),
+                        dry_run,
+                        force,
+                        hosts: docker_arguments.host.clone(),
+                        images,
+                        resumable,
+                        source_host,
+                        ssh_cli: Some(ssh_cli),
+                    })
+                },
+            )
+        }
 
         Subcommand::TunnelSsh {
+            command,
+            foreground,
+            local_address,
             local_socket,
             remote_socket,
             ssh_arguments,
             ssh_host,
-        } => tunnel_ssh::go(tunnel_ssh::In {
-            container_engine,
-            dry_run,
-            local_socket,
-            remote_socket,
-            ssh_cli: ssh_cli(&docker_arguments, &ssh_arguments),
-            ssh_host,
-        }),
+            stop,
+        } => {
+            let exit_code = with_ssh_cli(
+                &docker_arguments,
+                &ssh_arguments,
+                control_folder,
+                |ssh_cli| {
+                    tunnel_ssh::go(tunnel_ssh::In {
+                        command,
+                        container_engine,
+                        dry_run,
+                        foreground,
+                        local_address,
+                        local_socket,
+                        remote_socket,
+                        ssh_cli,
+                        ssh_host,
+                        stop,
+                    })
+                },
+            )?;
+
+            if exit_code != 0 {
+                drop(multiplexing);
+                process::exit(exit_code)
+            }
+            Ok(())
+        }
     }
 }
 
@@ -117,6 +413,11 @@ fn main() -> anyhow::Result<()> {
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Compose command to use, like `docker compose`, `podman compose`, or
+    /// `docker-compose`; detected if not given
+    #[arg(env, long, value_delimiter = ' ')]
+    compose_command: Vec<String>,
+
     /// Container engine program to use
     #[arg(default_value = "docker", env, long, value_enum)]
     container_engine: String,
@@ -153,7 +454,7 @@ struct DockerArguments {
 
     /// Daemon socket to connect to
     #[arg(long, short = 'H')]
-    host: Option<String>,
+    host: Vec<String>,
 
     /// Set the logging level
     #[arg(long, short = 'l', value_enum)]
@@ -183,6 +484,17 @@ struct DockerArguments {
 #[allow(clippy::large_enum_variant)]
 #[derive(clap::Subcommand)]
 enum Subcommand {
+    /// Save or load images of Compose project as offline bundle
+    ///
+    /// A bundle is a tar archive with the images referenced by a Compose
+    /// project, the resolved Compose config, and a manifest. It can be carried
+    /// to an air-gapped host, for example on a USB drive, where only images
+    /// missing there are loaded.
+    Bundle {
+        #[command(subcommand)]
+        subcommand: BundleSubcommand,
+    },
+
     // Keep following help in sync with this source, where applicable:
     // https://github.com/docker/compose/blob/main/docs/reference/compose_up.md
     /// Create or update Docker Compose services
@@ -244,6 +556,49 @@ enum Subcommand {
     ///{n}    $ kerek --dry-run deploy
     ///{n}- Show service config hashes:
     ///{n}    $ docker compose config --hash \*
+    ///
+    /// Multiple hosts, given by repeating `--host` or listed in an inventory file
+    /// via `--hosts-file`, are deployed to in turn. A host is unavailable from
+    /// the start of its deployment until all its services have their desired
+    /// number of up-to-date, running, and healthy replicas, waiting up to
+    /// `--wait-timeout` seconds (default: 60). At most `--max-unavailable-hosts`
+    /// hosts are unavailable at any time, so the next host starts as soon as one
+    /// is healthy. After a host fails, no further hosts start, and every failing
+    /// host is reported. A single host is deployed to without waiting for
+    /// health, whether given via `--host` or `--hosts-file`. Every host is
+    /// checked for a supported container engine and Compose version upfront.
+    ///
+    /// Example inventory file:
+    ///{n}
+    ///{n}    # inventory.toml
+    ///{n}    hosts = ["ssh://192.0.2.1", "ssh://192.0.2.2", "ssh://192.0.2.3"]
+    ///{n}    max-unavailable-hosts = 1
+    ///
+    /// Examples for multiple hosts:
+    ///{n}
+    ///{n}- Deploy to hosts of inventory file:
+    ///{n}    $ kerek deploy --hosts-file inventory.toml
+    ///{n}- Deploy to two hosts at the same time:
+    ///{n}    $ kerek --host ssh://a --host ssh://b deploy --max-unavailable-hosts 2
+    ///
+    /// With `--transfer-images`, images are delivered without a registry: they
+    /// are built locally if `--build` is given, images missing on the hosts are
+    /// transferred from the default daemon, and the deployment never pulls.
+    ///
+    /// Example for delivery without registry:
+    ///{n}
+    ///{n}- Build locally, transfer missing images, and deploy:
+    ///{n}    $ kerek --host ssh://a deploy --build --transfer-images
+    ///
+    /// For `ssh://` hosts, the Docker CLI connects with the SSH arguments given,
+    /// such as a custom SSH config file, unlike with vanilla Docker. Podman
+    /// ignores them, so they are rejected for Podman. An `--ssh-port` takes
+    /// precedence over the port of an `ssh://` host like `ssh://a:2222`.
+    ///
+    /// Example for custom SSH config file:
+    ///{n}
+    ///{n}- Deploy to SSH host of custom SSH config file:
+    ///{n}    $ kerek --host ssh://a deploy --ssh-config my_ssh_config
     Deploy {
         #[command(flatten)]
         docker_compose_arguments: DockerComposeArguments,
@@ -251,25 +606,162 @@ enum Subcommand {
         #[command(flatten)]
         docker_compose_up_arguments: DockerComposeUpArgumentsForDeploy,
 
+        /// Path to TOML inventory file listing hosts to deploy to
+        #[arg(long)]
+        hosts_file: Option<String>,
+
+        /// With `--prune-images`, number of newest unused images to keep per
+        /// repository
+        #[arg(default_value_t = 1, long)]
+        keep_revisions: u16,
+
+        /// Maximum number of hosts that are unavailable at any time [default: 1]
+        #[arg(long)]
+        max_unavailable_hosts: Option<u16>,
+
+        /// Remove images of Compose project that no container uses after
+        /// deploying, like `prune`
+        #[arg(long)]
+        prune_images: bool,
+
+        /// Services to consider
+        service_names: Vec<String>,
+
+        #[command(flatten)]
+        ssh_arguments: SshArguments,
+
+        /// Transfer images missing on hosts from default daemon, building them
+        /// first with `--build` and pulling those missing on default daemon,
+        /// then deploy with `--pull never`
+        #[arg(long)]
+        transfer_images: bool,
+    },
+
+    /// Show differences between running containers and Compose file
+    ///
+    /// This lists the changes that `deploy` would make, without applying them:
+    /// containers to be added (`+`) or removed (`-`) due to a service config
+    /// hash mismatch, a wrong number of replicas, orphan services, or missing
+    /// services, and up-to-date containers to be started (`!`) as they are not
+    /// running.
+    ///
+    /// With `--exit-code`, the exit status is 2 if there are differences, to
+    /// tell them apart from errors, which have exit status 1.
+    ///
+    /// Examples:
+    ///{n}
+    ///{n}- Show differences:
+    ///{n}    $ kerek diff
+    ///{n}- Exit with status 2 if there are differences, say, for monitoring:
+    ///{n}    $ kerek diff --exit-code
+    ///{n}- Show differences as JSON, say, for alerting:
+    ///{n}    $ kerek diff --format json
+    Diff {
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Exit with status 2 if there are differences, otherwise with 0
+        #[arg(long)]
+        exit_code: bool,
+
+        /// Output format
+        #[arg(default_value = "text", long, value_enum)]
+        format: diff::Format,
+
         /// Services to consider
         service_names: Vec<String>,
     },
 
+    /// Check container engine, Compose, and optionally an SSH host
+    ///
+    /// This reports the kind, version, API version, and rootless mode of the
+    /// container engine as well as the Compose command and its version.
+    ///
+    /// If an SSH host is given, it is checked instead, all via SSH, for what
+    /// `provision` sets up: SSH connection, container engine, Compose, remote
+    /// socket, membership in group `docker` (rootful Docker, except for user
+    /// root), and linger (rootless Podman).
+    ///
+    /// Failed checks come with a hint on how to fix them, and the exit status
+    /// is nonzero if any check fails.
+    ///
+    /// Examples:
+    ///{n}
+    ///{n}- Check environment of default Docker host:
+    ///{n}    $ kerek doctor
+    ///{n}- Check environment of Podman on SSH host:
+    ///{n}    $ kerek --container-engine podman doctor my-ssh-host
+    ///{n}- Check SSH host of custom SSH config file:
+    ///{n}    $ kerek doctor --ssh-config my_ssh_config my-ssh-host
+    Doctor {
+        #[command(flatten)]
+        ssh_arguments: SshArguments,
+
+        /// Reference like `[ssh://][<user>@]<hostname>[:<port>]`
+        ssh_host: Option<String>,
+    },
+
     #[command(hide = true)]
     DockerCliPluginMetadata,
 
+    /// Remove images of Compose project that no container uses
+    ///
+    /// Images of the project's repositories are removed unless a container uses
+    /// them, by removing their tags of these repositories. So are dangling
+    /// images labelled with the project by Compose, such as those built by it
+    /// and later untagged by a newer build under the same tag. Other dangling
+    /// images are left in place. The newest `--keep-revisions` unused images per
+    /// repository are kept for rollback.
+    ///
+    /// Examples:
+    ///{n}
+    ///{n}- Remove unused images, keeping the previous revision:
+    ///{n}    $ kerek prune
+    ///{n}- Remove all unused images:
+    ///{n}    $ kerek prune --keep-revisions 0
+    ///{n}- Only show what would be removed:
+    ///{n}    $ kerek --dry-run prune
+    Prune {
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Number of newest unused images to keep per repository
+        #[arg(default_value_t = 1, long)]
+        keep_revisions: u16,
+    },
+
     /// Install container engine on host, making system-wide changes
     ///
-    /// This targets a host via SSH, unless host `localhost` and no SSH config
-    /// file are passed as arguments, in which case the current machine is
-    /// targeted.
+    /// This targets a host via SSH, unless host `localhost` and no SSH
+    /// arguments are passed, in which case the current machine is targeted.
+    ///
+    /// Supported container engines are `docker`, installed from the official
+    /// apt repository of Docker on Debian or Ubuntu, and `podman`, set up for
+    /// rootless use.
+    ///
+    /// With pinned host keys, SSH accepts no other host keys, so keys of any
+    /// jump hosts must be pinned as well, and for a port other than 22, keys
+    /// are pinned like `[<hostname>]:<port> ssh-ed25519 AAAA…`.
     ///
     /// Examples:
     ///{n}
+    ///{n}- Provision Docker Engine on SSH host:
+    ///{n}    $ kerek provision my-ssh-host
     ///{n}- Provision Podman on SSH host:
     ///{n}    $ kerek --container-engine podman provision my-ssh-host
     ///{n}- Provision Podman on localhost:
     ///{n}    $ kerek --container-engine podman provision localhost
+    ///{n}- Provision Podman on SSH host behind bastion without SSH config file:
+    ///{n}    $ kerek --container-engine podman provision --ssh-identity ci_key \
+    ///{n}      --ssh-jump bastion --ssh-port 2222 \
+    ///{n}      --ssh-option StrictHostKeyChecking=accept-new my-ssh-host
+    ///{n}- Provision Podman on SSH host, accepting only pinned host key:
+    ///{n}    $ kerek --container-engine podman provision \
+    ///{n}      --ssh-known-host 'my-ssh-host ssh-ed25519 AAAA…' my-ssh-host
+    ///{n}- Record host key on first contact and verify it on later contacts:
+    ///{n}    $ kerek --container-engine podman provision \
+    ///{n}      --ssh-known-hosts-file known_hosts \
+    ///{n}      --ssh-option StrictHostKeyChecking=accept-new my-ssh-host
     Provision {
         /// Go ahead without prompting user to confirm
         #[arg(long)]
@@ -282,6 +774,29 @@ enum Subcommand {
         host: String,
     },
 
+    /// Show status of Docker Compose services without changing anything
+    ///
+    /// For each service, this shows the desired number of replicas, the number
+    /// of running containers, how many containers are up to date or stale by
+    /// service config hash, the health of containers, and the update order.
+    ///
+    /// Services with containers but no definition in the Compose file are
+    /// orphans, which have zero desired replicas.
+    ///
+    /// Examples:
+    ///{n}
+    ///{n}- Show status of all services:
+    ///{n}    $ kerek status
+    ///{n}- Show status of service `my-service`:
+    ///{n}    $ kerek status my-service
+    Status {
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Services to consider
+        service_names: Vec<String>,
+    },
+
     /// Copy images from default to specified Docker host
     ///
     /// By default, only images not present on the destination host are transferred. An
@@ -291,6 +806,16 @@ enum Subcommand {
     ///{n}- `<namespace>@<digest>`
     ///{n}- `<namespace>:<tag>@<digest>`
     ///
+    /// and the image has the same ID as on the source host. Thus, an image rebuilt
+    /// under the same tag like `latest` is transferred again.
+    ///
+    /// With multiple destination hosts given by repeating `--host`, each image is
+    /// saved once and loaded on all hosts missing it concurrently. Failures are
+    /// reported per host.
+    ///
+    /// Progress is shown for streamed transfers only, not with `--delta`,
+    /// `--direct`, or `--resumable`.
+    ///
     /// Examples:
     ///{n}
     ///{n}- Transfer image `img:tag` from default Docker host to 192.0.2.1 over SSH:
@@ -299,25 +824,85 @@ enum Subcommand {
     ///{n}    $ DOCKER_HOST=ssh://src kerek --host ssh://dest transfer-images img:tag
     ///{n}- Transfer image from Docker context `src` to `dest`:
     ///{n}    $ DOCKER_CONTEXT=src kerek --context dest transfer-images img:tag
+    ///{n}- Transfer image to two hosts, saving it only once:
+    ///{n}    $ kerek --host ssh://a --host ssh://b transfer-images img:tag
+    ///{n}- Transfer image to SSH host of custom SSH config file:
+    ///{n}    $ kerek --host ssh://a transfer-images --ssh-config my_ssh_config img:tag
     ///{n}
-    ///{n}- Always transfer image, even if already present under same name `img:tag`:
+    ///{n}- Always transfer image, even if already present with same ID:
     ///{n}    $ kerek --host … transfer-images --force img:tag
-    ///{n}- Transfer images of Compose file:
+    ///{n}- Transfer images of Compose project:
+    ///{n}    $ kerek --host … transfer-images --compose
+    ///{n}- Transfer images of Compose project for service `my-service` only:
+    ///{n}    $ kerek --host … transfer-images --compose my-service
+    ///{n}- Transfer images listed on stdin:
     ///{n}    $ docker compose config --images | kerek --host … transfer-images -
     ///{n}- Transfer image, compressing it in transit with Zstandard:
     ///{n}    $ kerek --host … transfer-images --compress zstd img:tag
+    ///{n}- Transfer image, compressing it with built-in Zstandard at level 19:
+    ///{n}    $ kerek --host … transfer-images --compress builtin:zstd:19 img:tag
+    ///{n}- Transfer image over flaky connection, resuming where it stopped if rerun:
+    ///{n}    $ kerek --host ssh://… transfer-images --resumable img:tag
+    ///{n}- Transfer only image layers missing on destination:
+    ///{n}    $ kerek --host … transfer-images --delta img:tag
+    ///{n}- Transfer image from SSH host `a` straight to `b`, bypassing localhost:
+    ///{n}    $ DOCKER_HOST=ssh://a kerek --host ssh://b transfer-images --direct img:tag
     TransferImages {
-        /// Compression command to use (`bzip2`, `gzip`, `xz`, `zstd`, etc.)
+        /// Transfer images of Compose project, including images of buildable
+        /// services without explicit tag
+        #[arg(long)]
+        compose: bool,
+
+        /// Compression command to use (`bzip2`, `gzip`, `xz`, `zstd`, etc.), or
+        /// built-in compression without external command like `builtin:gzip`
+        /// or `builtin:zstd` with optional level like `builtin:zstd:19`
         #[arg(long, value_delimiter = ' ')]
         compress: Vec<String>,
 
+        /// Send only layers that the destination does not have yet; this
+        /// buffers the saved image in a temporary file and needs the classic
+        /// image store of Docker Engine on the destination, otherwise all
+        /// layers are sent as partial images are rejected by the containerd
+        /// image store and by Podman
+        #[arg(long)]
+        delta: bool,
+
+        /// Pipe images from source to destination over SSH run on the source
+        /// host, without passing through localhost; this needs SSH hosts as
+        /// source and destination, Bash on the source host, and the source
+        /// host must be able to connect to the destination, otherwise images
+        /// are relayed via localhost
+        #[arg(long)]
+        direct: bool,
+
         /// Copy images without checking if the destination already has such
-        /// images; useful for replacing images with `latest` tag
+        /// images; useful to replace an image on the destination that has the
+        /// matching ID but is corrupted, like with missing layers
         #[arg(long)]
         force: bool,
 
-        /// Images to copy; use `-` to pass image names as stdin lines
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Images to copy; use `-` to pass image names as stdin lines; with
+        /// `--compose`, services to copy images of instead, or all if none
         images: Vec<String>,
+
+        /// Stage images on destination in chunks verified by SHA-256 before
+        /// loading them; an interrupted transfer resumes with the staged
+        /// chunks when run again, provided that saving (and compressing)
+        /// outputs the same archive again; staging folders in
+        /// `~/.cache/kerek/transfers` untouched for 7 days are removed; this
+        /// needs SSH hosts as destination
+        #[arg(long)]
+        resumable: bool,
+
+        /// Docker host to copy images from instead of default Docker host
+        #[arg(env = "DOCKER_HOST", long)]
+        source_host: Option<String>,
+
+        #[command(flatten)]
+        ssh_arguments: SshArguments,
     },
 
     /// Forward local Unix domain socket to remote Docker host over SSH
@@ -335,7 +920,59 @@ enum Subcommand {
     ///{n}    $ fuser --kill -TERM kerek.sock
     ///{n}- Tunnel to SSH host of custom SSH config file:
     ///{n}    $ kerek tunnel-ssh --ssh-config my_ssh_config my-ssh-host
+    ///
+    /// With `--foreground`, Kerek supervises the SSH tunnel instead: it pings the
+    /// Docker host through the forwarded socket every 10 seconds and reconnects
+    /// whenever SSH exits or pings keep failing. It gives up if the tunnel does
+    /// not become healthy in the first 5 attempts, like with an authentication
+    /// error. The PIDs are recorded in a file next to the local socket like
+    /// `kerek.sock.pid`, which `--stop` uses to tear down the tunnel, checking
+    /// that the processes are still the tunnel's.
+    ///
+    /// Examples for supervised tunnel:
+    ///{n}
+    ///{n}- Supervise SSH tunnel in background:
+    ///{n}    $ kerek tunnel-ssh --foreground my-ssh-host &
+    ///{n}    $ DOCKER_HOST="unix://${PWD}/kerek.sock" docker ps
+    ///{n}    $ kerek tunnel-ssh --stop
+    ///
+    /// Given a command after `--`, Kerek runs it with `DOCKER_HOST` pointed at a
+    /// tunnel on a private temporary socket instead. The tunnel is torn down
+    /// afterwards in any case, also on SIGINT or SIGTERM, which Kerek forwards
+    /// to the command, and Kerek exits with the exit code of the command.
+    ///
+    /// Examples for scoped tunnel:
+    ///{n}
+    ///{n}- Show containers running on SSH host:
+    ///{n}    $ kerek tunnel-ssh my-ssh-host -- docker ps
+    ///{n}- Show Compose services on SSH host:
+    ///{n}    $ kerek tunnel-ssh my-ssh-host -- docker compose ps
+    ///
+    /// For tools that only talk TCP, `--local-address` forwards a loopback TCP
+    /// port instead of a Unix domain socket. Beware that this port is
+    /// unauthenticated: any local user or process can use it to control the
+    /// Docker host.
+    ///
+    /// Examples for TCP:
+    ///{n}
+    ///{n}- Tunnel to SSH host via TCP port 2375:
+    ///{n}    $ kerek tunnel-ssh --local-address 127.0.0.1:2375 my-ssh-host
+    ///{n}    $ DOCKER_HOST=tcp://127.0.0.1:2375 docker ps
+    ///{n}- Tunnel to SSH host via free TCP port:
+    ///{n}    $ DOCKER_HOST="$(kerek tunnel-ssh --local-address 127.0.0.1:0 my-host)"
     TunnelSsh {
+        /// Run SSH tunnel supervised in foreground until stopped, reconnecting
+        /// on failures
+        #[arg(long)]
+        foreground: bool,
+
+        /// Loopback address like `127.0.0.1:2375` to forward instead of Unix
+        /// domain socket; port 0 picks a free port, printed as Docker host,
+        /// which another process may take before SSH binds it in rare cases;
+        /// stop such a tunnel with `--local-address` of port 0, too
+        #[arg(long)]
+        local_address: Option<net::SocketAddr>,
+
         /// Path to Unix domain socket on localhost to be forwarded
         #[arg(default_value = "kerek.sock", long)]
         local_socket: String,
@@ -348,7 +985,71 @@ enum Subcommand {
         ssh_arguments: SshArguments,
 
         /// Reference like `[ssh://][<user>@]<hostname>[:<port>]`
-        ssh_host: String,
+        #[arg(required_unless_present = "stop")]
+        ssh_host: Option<String>,
+
+        /// Command to run with `DOCKER_HOST` pointed at temporary SSH tunnel
+        #[arg(conflicts_with_all = ["foreground", "stop"], last = true)]
+        command: Vec<String>,
+
+        /// Tear down tunnel supervised via `--foreground` for local socket
+        #[arg(conflicts_with = "foreground", long)]
+        stop: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum BundleSubcommand {
+    /// Save images of Docker Compose project with its config as bundle
+    ///
+    /// The bundle includes the images of `docker compose config --images` and
+    /// the resolved config of `docker compose config`.
+    ///
+    /// Examples:
+    ///{n}
+    ///{n}- Create bundle compressed with Zstandard:
+    ///{n}    $ kerek bundle create --out release.tar.zst
+    ///{n}- Create bundle with images of service `my-service` only:
+    ///{n}    $ kerek bundle create --out release.tar my-service
+    Create {
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Path of bundle to create; compressed with gzip for extension `.gz`
+        /// or `.tgz`, and with Zstandard for extension `.zst` or `.tzst`
+        #[arg(long)]
+        out: String,
+
+        /// Services to include images of
+        service_names: Vec<String>,
+    },
+
+    /// Load images of bundle missing on Docker host
+    ///
+    /// Images already present under the same name are skipped.
+    ///
+    /// Examples:
+    ///{n}
+    ///{n}- Load missing images of bundle:
+    ///{n}    $ kerek bundle load release.tar.zst
+    ///{n}- Load missing images, then deploy Compose config of bundle:
+    ///{n}    $ kerek bundle load --deploy release.tar.zst
+    ///{n}- Deploy Compose config of bundle under another project name:
+    ///{n}    $ kerek bundle load --deploy --project-name staging release.tar.zst
+    ///
+    /// Compose options like `--project-name` and `--profile` apply when
+    /// deploying, whereas the Compose file always comes from the bundle.
+    Load {
+        /// Deploy Compose config of bundle after loading images, without
+        /// building or pulling images
+        #[arg(long)]
+        deploy: bool,
+
+        #[command(flatten)]
+        docker_compose_arguments: DockerComposeArguments,
+
+        /// Path of bundle to load
+        bundle: String,
     },
 }
 
@@ -357,6 +1058,56 @@ struct SshArguments {
     /// Path to SSH config file
     #[arg(long, short = 'F')]
     ssh_config: Option<String>,
+
+    /// Path to SSH identity file (private key)
+    #[arg(long)]
+    ssh_identity: Option<String>,
+
+    /// SSH jump host like `[<user>@]<hostname>[:<port>]`, or comma-separated
+    /// jump hosts
+    #[arg(long)]
+    ssh_jump: Option<String>,
+
+    /// SSH host key to pin like `<hostname> ssh-ed25519 AAAA…`, as in
+    /// known_hosts file, with strict host key checking unless set otherwise
+    /// via `--ssh-option StrictHostKeyChecking=…`
+    #[arg(long)]
+    ssh_known_host: Vec<String>,
+
+    /// Path to known_hosts file with SSH host keys to pin, with strict host key
+    /// checking unless set otherwise via `--ssh-option StrictHostKeyChecking=…`
+    #[arg(long)]
+    ssh_known_hosts_file: Option<String>,
+
+    /// SSH option like `StrictHostKeyChecking=accept-new`, as in SSH config
+    #[arg(long, value_parser = parse_ssh_option)]
+    ssh_option: Vec<String>,
+
+    /// Port to connect to on SSH host, taking precedence over the port of an
+    /// `ssh://` host
+    #[arg(long)]
+    ssh_port: Option<u16>,
+}
+
+impl SshArguments {
+    fn is_set(&self) -> bool {
+        let Self {
+            ssh_config,
+            ssh_identity,
+            ssh_jump,
+            ssh_known_host,
+            ssh_known_hosts_file,
+            ssh_option,
+            ssh_port,
+        } = self;
+        ssh_config.is_some()
+            || ssh_identity.is_some()
+            || ssh_jump.is_some()
+            || !ssh_known_host.is_empty()
+            || ssh_known_hosts_file.is_some()
+            || !ssh_option.is_empty()
+            || ssh_port.is_some()
+    }
 }
 
 // Top-level Docker Compose arguments.
@@ -510,8 +1261,9 @@ impl<'a> From<&'a DockerArguments> for docker::Arguments<'a> {
             config: config.as_deref(),
             context: context.as_deref(),
             debug: *debug,
-            host: host.as_deref(),
+            host: host.first().map(|host| host.as_str()),
             log_level: *log_level,
+            ssh_wrapper_folder: None,
             tls: *tls,
             tlscacert: tlscacert.as_deref(),
             tlscert: tlscert.as_deref(),
@@ -551,17 +1303,120 @@ impl<'a> From<&'a DockerComposeArguments> for docker_compose::Arguments<'a> {
     }
 }
 
+// Runs with SSH CLI of given arguments, explaining SSH errors when pinning.
+fn with_ssh_cli<T>(
+    docker_arguments: &DockerArguments,
+    ssh_arguments: &SshArguments,
+    control_folder: Option<&path::Path>,
+    go: impl FnOnce(ssh::Cli) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let known_hosts = ssh::KnownHosts::new(
+        &ssh_arguments.ssh_known_host,
+        ssh_arguments.ssh_known_hosts_file.as_deref(),
+    )?;
+    go(ssh_cli(
+        docker_arguments,
+        &known_hosts,
+        ssh_arguments,
+        control_folder,
+    ))
+    .map_err(|error| known_hosts.explain_failure(error))
+}
+
+// Podman connects to `ssh://` hosts with an SSH client of its own, which
+// ignores the `ssh` wrapper of multiplexing.
+fn check_docker_ssh_arguments(
+    container_engine: &str,
+    may_use_ssh: bool,
+    ssh_arguments: &SshArguments,
+) -> anyhow::Result<()> {
+    if may_use_ssh
+        && ssh_arguments.is_set()
+        && docker::EngineKind::from_program(container_engine) == docker::EngineKind::Podman
+    {
+        return Err(anyhow::anyhow!(
+            "Podman ignores SSH arguments like `--ssh-config` for `ssh://` hosts; \
+             configure the connection with `podman system connection add` instead"
+        ));
+    }
+    Ok(())
+}
+
+// Whether a run connects via SSH, be it Docker to an `ssh://` host or context,
+// or Kerek itself to an SSH host, so that local runs leave `PATH` alone.
+fn uses_ssh(subcommand: &Subcommand, docker_arguments: &DockerArguments) -> bool {
+    let docker_uses_ssh = docker_arguments
+        .host
+        .iter()
+        .any(|host| host.starts_with("ssh://"))
+        || docker::Arguments::from(docker_arguments).may_use_ssh();
+
+    match subcommand {
+        Subcommand::Bundle { .. }
+        | Subcommand::Diff { .. }
+        | Subcommand::Prune { .. }
+        | Subcommand::Status { .. } => docker_uses_ssh,
+        // Hosts of an inventory file are typically SSH hosts.
+        Subcommand::Deploy { hosts_file, .. } => docker_uses_ssh || hosts_file.is_some(),
+        Subcommand::Doctor { ssh_host, .. } => docker_uses_ssh || ssh_host.is_some(),
+        Subcommand::DockerCliPluginMetadata => false,
+        Subcommand::Provision {
+            host,
+            ssh_arguments,
+            ..
+        } => host != "localhost" || ssh_arguments.is_set(),
+        Subcommand::TransferImages {
+            direct,
+            resumable,
+            source_host,
+            ..
+        } => {
+            docker_uses_ssh
+                || source_host
+                    .as_ref()
+                    .is_some_and(|host| host.starts_with("ssh://"))
+                || *direct
+                || *resumable
+        }
+        Subcommand::TunnelSsh { stop, .. } => !stop,
+    }
+}
+
 fn ssh_cli<'a>(
     docker_arguments: &'a DockerArguments,
-    SshArguments { ssh_config }: &'a SshArguments,
+    known_hosts: &'a ssh::KnownHosts,
+    SshArguments {
+        ssh_config,
+        ssh_identity,
+        ssh_jump,
+        ssh_option,
+        ssh_port,
+        ..
+    }: &'a SshArguments,
+    control_folder: Option<&'a path::Path>,
 ) -> ssh::Cli<'a> {
     ssh::Cli::new(ssh::Arguments {
         config: ssh_config.as_deref(),
+        control_folder,
         debug: docker_arguments.debug,
+        identity: ssh_identity.as_deref(),
+        jump: ssh_jump.as_deref(),
+        known_hosts: known_hosts.files(),
         log_level: docker_arguments.log_level,
+        options: ssh_option,
+        port: *ssh_port,
     })
 }
 
+fn parse_ssh_option(option: &str) -> Result<String, String> {
+    match option.split_once('=') {
+        Some((key, _)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+            Ok(option.into())
+        }
+        _ => Err("expected `KEY=VALUE`".into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -599,8 +1454,15 @@ mod tests {
     }
 
     #[test_case::test_case(&[]; "")]
+    #[test_case::test_case(&["bundle"]; "bundle")]
+    #[test_case::test_case(&["bundle", "create"]; "bundle create")]
+    #[test_case::test_case(&["bundle", "load"]; "bundle load")]
     #[test_case::test_case(&["deploy"]; "deploy")]
+    #[test_case::test_case(&["diff"]; "diff")]
+    #[test_case::test_case(&["doctor"]; "doctor")]
     #[test_case::test_case(&["provision"]; "provision")]
+    #[test_case::test_case(&["prune"]; "prune")]
+    #[test_case::test_case(&["status"]; "status")]
     #[test_case::test_case(&["transfer-images"]; "transfer-images")]
     #[test_case::test_case(&["tunnel-ssh"]; "tunnel-ssh")]
     fn readme_includes_subcommand_help(subcommands: &[&str]) {
@@ -627,4 +1489,13 @@ mod tests {
             .collect::<Vec<_>>()
             .join("\n")
     }
+
+    #[test_case::test_case("StrictHostKeyChecking=accept-new", true; "option")]
+    #[test_case::test_case("SetEnv=A=B", true; "value with equals sign")]
+    #[test_case::test_case("StrictHostKeyChecking", false; "no value")]
+    #[test_case::test_case("=yes", false; "no key")]
+    #[test_case::test_case("Strict HostKeyChecking=yes", false; "space in key")]
+    fn parses_ssh_option(option: &str, is_valid: bool) {
+        assert_eq!(parse_ssh_option(option).is_ok(), is_valid)
+    }
 }
@@ -0,0 +1,206 @@
+use super::log;
+use std::io;
+use std::io::IsTerminal;
+use std::time;
+
+const BAR_WIDTH: usize = 30;
+const LOG_INTERVAL: time::Duration = time::Duration::from_secs(10);
+const TERMINAL_INTERVAL: time::Duration = time::Duration::from_millis(200);
+
+// Reports progress of a byte stream, as a bar redrawn in place on a terminal,
+// otherwise as periodic log lines.
+pub struct Progress {
+    count: u64,
+    interval: Option<time::Duration>,
+    is_terminal: bool,
+    label: String,
+    last_report: time::Instant,
+    start: time::Instant,
+    total: Option<u64>,
+}
+
+pub struct Reader<'a, T> {
+    progress: &'a mut Progress,
+    reader: T,
+}
+
+impl Progress {
+    pub fn new(label: String, total: Option<u64>) -> Self {
+        let is_terminal = io::stderr().is_terminal();
+        let interval = (log::level() <= log::Level::Info).then_some(if is_terminal {
+            TERMINAL_INTERVAL
+        } else {
+            LOG_INTERVAL
+        });
+        let now = time::Instant::now();
+
+        Self {
+            count: 0,
+            interval,
+            is_terminal,
+            label,
+            last_report: now,
+            start: now,
+            total,
+        }
+    }
+
+    pub fn add(&mut self, count: u64) {
+        self.count += count;
+
+        if let Some(interval) = self.interval {
+            let now = time::Instant::now();
+            if now - self.last_report >= interval {
+                self.last_report = now;
+                self.report();
+            }
+        }
+    }
+
+    pub fn finish(&mut self) {
+        if self.interval.is_some() && self.is_terminal {
+            self.report();
+            eprintln!();
+        }
+    }
+
+    pub fn reader<T: io::Read>(&mut self, reader: T) -> Reader<'_, T> {
+        Reader {
+            progress: self,
+            reader,
+        }
+    }
+
+    fn report(&self) {
+        let line = render(
+            &self.label,
+            self.count,
+            self.total,
+            self.start.elapsed(),
+            self.is_terminal,
+        );
+        if self.is_terminal {
+            eprint!("\r\x1b[2K{line}");
+        } else {
+            log::info!("{line}");
+        }
+    }
+}
+
+impl<T: io::Read> io::Read for Reader<'_, T> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = self.reader.read(buffer)?;
+        self.progress.add(count.try_into().unwrap_or(u64::MAX));
+        Ok(count)
+    }
+}
+
+fn render(
+    label: &str,
+    count: u64,
+    total: Option<u64>,
+    elapsed: time::Duration,
+    has_bar: bool,
+) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 {
+        count as f64 / seconds
+    } else {
+        0.0
+    };
+    let count_text = format_bytes(count as f64);
+    let rate_text = format_bytes(rate);
+
+    match total.filter(|&total| total > 0) {
+        None => format!("{label}: {count_text} at {rate_text}/s"),
+        Some(total) => {
+            let fraction = (count as f64 / total as f64).min(1.0);
+            let percent = (fraction * 100.0).round();
+            let total_text = format_bytes(total as f64);
+            let eta = if rate > 0.0 {
+                format_duration(total.saturating_sub(count) as f64 / rate)
+            } else {
+                "–".into()
+            };
+            let bar = if has_bar {
+                let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+                let empty = BAR_WIDTH - filled;
+                format!("[{}{}] ", "#".repeat(filled), " ".repeat(empty))
+            } else {
+                String::new()
+            };
+            format!(
+                "{label}: {bar}{percent}% of {total_text}, \
+                {count_text} at {rate_text}/s, ETA {eta}"
+            )
+        }
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    let (value, unit) = [("kB", 1e3), ("MB", 1e6), ("GB", 1e9), ("TB", 1e12)]
+        .into_iter()
+        .rev()
+        .find(|(_, factor)| bytes >= *factor)
+        .map_or((bytes, "B"), |(unit, factor)| (bytes / factor, unit));
+
+    if unit == "B" {
+        format!("{value:.0} {unit}")
+    } else {
+        format!("{value:.1} {unit}")
+    }
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{seconds}s"),
+        (0, minutes, seconds) => format!("{minutes}m {seconds:02}s"),
+        (hours, minutes, _) => format!("{hours}h {minutes:02}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case(None, false, "Image: 2.5 MB at 1.2 MB/s"; "unknown total")]
+    #[test_case::test_case(
+        Some(10_000_000),
+        false,
+        "Image: 25% of 10.0 MB, 2.5 MB at 1.2 MB/s, ETA 6s";
+        "log"
+    )]
+    #[test_case::test_case(
+        Some(10_000_000),
+        true,
+        "Image: [########                      ] 25% of 10.0 MB, 2.5 MB at 1.2 MB/s, ETA 6s";
+        "bar"
+    )]
+    fn renders(total: Option<u64>, has_bar: bool, expected: &str) {
+        assert_eq!(
+            render(
+                "Image",
+                2_500_000,
+                total,
+                time::Duration::from_secs(2),
+                has_bar,
+            ),
+            expected,
+        )
+    }
+
+    #[test_case::test_case(999.0, "999 B"; "bytes")]
+    #[test_case::test_case(1_234.0, "1.2 kB"; "kilobytes")]
+    #[test_case::test_case(5_678_900_000.0, "5.7 GB"; "gigabytes")]
+    fn formats_bytes(bytes: f64, expected: &str) {
+        assert_eq!(format_bytes(bytes), expected)
+    }
+
+    #[test_case::test_case(7.4, "7s"; "seconds")]
+    #[test_case::test_case(65.0, "1m 05s"; "minutes")]
+    #[test_case::test_case(7_380.0, "2h 03m"; "hours")]
+    fn formats_duration(seconds: f64, expected: &str) {
+        assert_eq!(format_duration(seconds), expected)
+    }
+}
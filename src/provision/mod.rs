@@ -10,7 +10,7 @@ pub fn go(
         container_engine,
         dry_run,
         force,
-        has_ssh_config_override,
+        has_ssh_override,
         host,
         ssh_cli,
     }: In,
@@ -26,7 +26,7 @@ pub fn go(
         log::info!("Would provision host {host:?}.");
         Ok(())
     } else {
-        let mut command = if host == "localhost" && !has_ssh_config_override {
+        let mut command = if host == "localhost" && !has_ssh_override {
             let mut command = process::Command::new("bash");
             command.env("CONTAINER_ENGINE", container_engine);
             command
@@ -48,7 +48,7 @@ pub struct In<'a> {
     pub container_engine: String,
     pub dry_run: bool,
     pub force: bool,
-    pub has_ssh_config_override: bool,
+    pub has_ssh_override: bool,
     pub host: String,
     pub ssh_cli: ssh::Cli<'a>,
 }
@@ -2,18 +2,68 @@
 
 set -o errexit -o nounset -o pipefail
 
-if [[ "${CONTAINER_ENGINE}" != 'podman' ]]; then
-  echo "Unsupported container engine: ${CONTAINER_ENGINE}"
-  exit 1
-fi
+provision_docker() {
+  local distribution
+  local codename
+  distribution="$(. /etc/os-release && echo "${ID}")"
+  codename="$(. /etc/os-release && echo "${VERSION_CODENAME}")"
 
-sudo apt-get --yes install podman
+  case "${distribution}" in
+    debian | ubuntu) ;;
+    *)
+      echo "Unsupported distribution for Docker Engine: ${distribution}"
+      exit 1
+      ;;
+  esac
 
-systemctl --user enable --now podman
+  # Official apt repository, see https://docs.docker.com/engine/install/.
+  sudo apt-get update
+  sudo apt-get --yes install ca-certificates curl
+  sudo install --directory --mode 0755 /etc/apt/keyrings
 
-sudo loginctl enable-linger
+  sudo curl --fail --location --show-error --silent \
+    --output /etc/apt/keyrings/docker.asc \
+    "https://download.docker.com/linux/${distribution}/gpg"
+  sudo chmod a+r /etc/apt/keyrings/docker.asc
+  echo "deb [arch=$(dpkg --print-architecture) \
+signed-by=/etc/apt/keyrings/docker.asc] \
+https://download.docker.com/linux/${distribution} ${codename} stable" \
+    | sudo tee /etc/apt/sources.list.d/docker.list
 
-sudo sysctl --write net.ipv4.ip_unprivileged_port_start=80 \
-  | sudo tee /etc/sysctl.d/99-custom.conf
+  sudo apt-get update
+  sudo apt-get --yes install containerd.io docker-buildx-plugin docker-ce \
+    docker-ce-cli docker-compose-plugin
 
-podman system prune --all --filter until=720h --force
+  sudo usermod --append --groups docker "${USER}"
+
+  sudo systemctl enable --now docker
+
+  # Group membership only applies to new sessions, hence `sudo`.
+  sudo docker version
+}
+
+provision_podman() {
+  sudo apt-get --yes install podman
+
+  systemctl --user enable --now podman
+
+  sudo loginctl enable-linger
+
+  sudo sysctl --write net.ipv4.ip_unprivileged_port_start=80 \
+    | sudo tee /etc/sysctl.d/99-custom.conf
+
+  podman system prune --all --filter until=720h --force
+}
+
+case "${CONTAINER_ENGINE}" in
+  docker)
+    provision_docker
+    ;;
+  podman)
+    provision_podman
+    ;;
+  *)
+    echo "Unsupported container engine: ${CONTAINER_ENGINE}"
+    exit 1
+    ;;
+esac
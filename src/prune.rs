@@ -0,0 +1,362 @@
+use super::command;
+use super::deploy;
+use super::docker;
+use super::docker_compose;
+use super::log;
+use super::transfer_images;
+use anyhow::Context;
+use std::collections;
+
+pub fn go(input: In) -> anyhow::Result<()> {
+    deploy::probe(&input.docker_cli, &input.docker_compose_cli)?;
+    remove_unused_images(input)
+}
+
+// Like `go` but without probing, for callers that have probed already.
+pub fn remove_unused_images(
+    In {
+        docker_cli,
+        docker_compose_cli,
+        dry_run,
+        keep_revisions,
+    }: In,
+) -> anyhow::Result<()> {
+    let actual_containers = deploy::get_actual_state::go(&[], &docker_cli, &docker_compose_cli)?;
+    let used_image_ids = actual_containers
+        .into_iter()
+        .map(|container| container.image_id)
+        .collect();
+    let transfer_images::compose::Project {
+        images: project_images,
+        name: project_name,
+    } = transfer_images::compose::get_project(&docker_compose_cli, &[])
+        .context("Unable to get images of Compose project")?;
+    let repositories = project_images
+        .iter()
+        .map(|image| get_repository(image).into())
+        .collect();
+    let images = get_images(&docker_cli).context("Unable to list images")?;
+
+    for image in plan_removals(
+        images,
+        &project_name,
+        &repositories,
+        &used_image_ids,
+        keep_revisions,
+    )? {
+        let name = image.describe();
+
+        if dry_run {
+            log::info!("Would remove image {name}.");
+            continue;
+        }
+
+        log::info!("Removing image {name}.");
+        // Images used by containers of other projects are left in place.
+        if let Err(error) = command::status_ok(
+            docker_cli
+                .command()
+                .args(["image", "rm", "--"])
+                .args(get_references(&image, &repositories)),
+        ) {
+            log::warning!("Unable to remove image {name}: {error:#}");
+        }
+    }
+
+    Ok(())
+}
+
+pub struct In<'a> {
+    pub docker_cli: docker::Cli<'a>,
+    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub dry_run: bool,
+    pub keep_revisions: u16,
+}
+
+// Output of `docker image inspect`.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Image {
+    #[serde(default)]
+    config: ImageConfig,
+    created: String,
+    id: String,
+    repo_tags: Option<Vec<String>>,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageConfig {
+    labels: Option<collections::BTreeMap<String, String>>,
+}
+
+impl Image {
+    fn describe(&self) -> String {
+        match self.repo_tags.as_deref() {
+            None | Some([]) => format!("{:?}", self.id),
+            Some(repo_tags) => format!("{:?} ({repo_tags:?})", self.id),
+        }
+    }
+}
+
+fn get_images(docker_cli: &docker::Cli) -> anyhow::Result<Vec<Image>> {
+    let image_ids =
+        command::stdout_utf8(
+            docker_cli
+                .command()
+                .args(["images", "--no-trunc", "--quiet"]),
+        )?;
+    let image_ids = image_ids.lines().collect::<collections::BTreeSet<_>>();
+
+    if image_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let images = command::stdout_json::<Vec<Image>>(
+        docker_cli
+            .command()
+            .args(["image", "inspect", "--"])
+            .args(image_ids),
+    )?;
+    Ok(images
+        .into_iter()
+        .map(|image| Image {
+            id: docker::normalize_image_id(&image.id),
+            ..image
+        })
+        .collect())
+}
+
+// Plans to remove images of the project's repositories and its dangling images,
+// such as those untagged by loading a newer image under the same tag. Dangling
+// images count as the project's only if labelled so by Compose, as when built
+// by it. Images used by a container are kept, as are the newest unused images
+// per repository for rollback.
+fn plan_removals(
+    images: Vec<Image>,
+    project_name: &str,
+    repositories: &collections::BTreeSet<String>,
+    used_image_ids: &collections::BTreeSet<String>,
+    keep_revisions: u16,
+) -> anyhow::Result<Vec<Image>> {
+    let mut images = images
+        .into_iter()
+        .map(|image| Ok((parse_timestamp(&image.created)?, image)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    images.sort_by(|(left, _), (right, _)| right.cmp(left));
+    let mut kept_revisions = collections::BTreeMap::<Option<String>, u16>::new();
+    let mut removals = vec![];
+
+    for (_, image) in images {
+        let image_repositories = match image.repo_tags.as_deref() {
+            None | Some([]) => {
+                let image_project = image
+                    .config
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get("com.docker.compose.project"));
+                if image_project.is_some_and(|image_project| image_project == project_name) {
+                    vec![None]
+                } else {
+                    vec![]
+                }
+            }
+            Some(repo_tags) => repo_tags
+                .iter()
+                .map(|repo_tag| get_repository(repo_tag))
+                .filter(|repository| repositories.contains(*repository))
+                .map(|repository| Some(repository.into()))
+                .collect(),
+        };
+
+        if image_repositories.is_empty() || used_image_ids.contains(&image.id) {
+            continue;
+        }
+
+        let mut is_kept = false;
+        for repository in image_repositories {
+            let count = kept_revisions.entry(repository).or_default();
+            if *count < keep_revisions {
+                *count += 1;
+                is_kept = true;
+            }
+        }
+
+        if !is_kept {
+            removals.push(image);
+        }
+    }
+
+    Ok(removals)
+}
+
+// Removing an image by ID fails if it has multiple tags, so the tags of the
+// project's repositories are removed instead, which removes the image with its
+// last tag. Tags of other repositories are left in place.
+fn get_references(image: &Image, repositories: &collections::BTreeSet<String>) -> Vec<String> {
+    let references = image
+        .repo_tags
+        .iter()
+        .flatten()
+        .filter(|repo_tag| repositories.contains(get_repository(repo_tag)))
+        .cloned()
+        .collect::<Vec<_>>();
+    if references.is_empty() {
+        vec![image.id.clone()]
+    } else {
+        references
+    }
+}
+
+// Parses an RFC 3339 timestamp into seconds and nanoseconds since the Unix
+// epoch, as strings only sort chronologically with the same offset and
+// fraction width.
+fn parse_timestamp(timestamp: &str) -> anyhow::Result<(i64, u32)> {
+    (|| {
+        let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+        let (time, offset_seconds) = match time.strip_suffix(['Z', 'z']) {
+            Some(time) => (time, 0),
+            None => {
+                let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+                let [hours, minutes] = parse_fields(&offset[1..], ':')?;
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                (time, sign * (hours * 60 + minutes) * 60)
+            }
+        };
+        let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+
+        let [year, month, day] = parse_fields(date, '-')?;
+        let [hour, minute, second] = parse_fields(time, ':')?;
+        if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let nanoseconds = format!("{:0<9.9}", fraction).parse().ok()?;
+
+        let days = days_from_civil(year, month, day);
+        let seconds = ((days * 24 + hour) * 60 + minute) * 60 + second - offset_seconds;
+        Some((seconds, nanoseconds))
+    })()
+    .with_context(|| format!("Invalid timestamp {timestamp:?}"))
+}
+
+fn parse_fields<const N: usize>(text: &str, separator: char) -> Option<[i64; N]> {
+    text.split(separator)
+        .map(|field| field.parse().ok())
+        .collect::<Option<Vec<_>>>()?
+        .try_into()
+        .ok()
+}
+
+// Days since the Unix epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn get_repository(image: &str) -> &str {
+    let image = image.split_once('@').map_or(image, |(name, _)| name);
+    match image.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => repository,
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case("alpine", "alpine"; "name only")]
+    #[test_case::test_case("alpine:3.22", "alpine"; "tag")]
+    #[test_case::test_case("localhost:5000/app:1", "localhost:5000/app"; "registry port")]
+    #[test_case::test_case("localhost:5000/app", "localhost:5000/app"; "registry port only")]
+    #[test_case::test_case("app:1@sha256:abc", "app"; "digest")]
+    fn gets_repository(image: &str, expected: &str) {
+        assert_eq!(get_repository(image), expected)
+    }
+
+    #[test_case::test_case(0, &["3", "4", "5", "6"]; "keep none")]
+    #[test_case::test_case(1, &["4", "6"]; "keep one")]
+    #[test_case::test_case(2, &[]; "keep two")]
+    fn plans_removals(keep_revisions: u16, expected: &[&str]) -> anyhow::Result<()> {
+        let images = vec![
+            image("0", "2025-01-06T00:00:00Z", Some(&["app:latest"]), None),
+            image("1", "2025-01-05T00:00:00Z", Some(&["other:latest"]), None),
+            image("2", "2025-01-04T00:00:00Z", Some(&["app:2"]), None),
+            image("3", "2025-01-03T01:00:00+01:00", Some(&["app:1"]), None),
+            image("4", "2025-01-02T00:00:00.5Z", Some(&["app:0"]), None),
+            image("5", "2025-01-02T00:00:00Z", Some(&[]), Some("my-project")),
+            image("6", "2025-01-01T00:00:00Z", None, Some("my-project")),
+            image("7", "2025-01-01T00:00:00Z", None, Some("other-project")),
+            image("8", "2025-01-01T00:00:00Z", None, None),
+        ];
+
+        let removals = plan_removals(
+            images,
+            "my-project",
+            &["app".into()].into(),
+            &["0".into(), "2".into()].into(),
+            keep_revisions,
+        )?;
+
+        assert_eq!(
+            removals
+                .iter()
+                .map(|image| image.id.as_str())
+                .collect::<Vec<_>>(),
+            expected,
+        );
+        Ok(())
+    }
+
+    #[test_case::test_case(Some(&["app:1", "app:2", "other:1"]), &["app:1", "app:2"]; "tags")]
+    #[test_case::test_case(Some(&[]), &["0"]; "no tags")]
+    #[test_case::test_case(None, &["0"]; "null tags")]
+    fn gets_references(repo_tags: Option<&[&str]>, expected: &[&str]) {
+        assert_eq!(
+            get_references(
+                &image("0", "2025-01-01T00:00:00Z", repo_tags, None),
+                &["app".into()].into(),
+            ),
+            expected,
+        )
+    }
+
+    #[test_case::test_case("1970-01-01T00:00:00Z", (0, 0); "epoch")]
+    #[test_case::test_case("2025-01-06T12:34:56Z", (1_736_166_896, 0); "UTC")]
+    #[test_case::test_case(
+        "2025-01-06T13:34:56.123456789+01:00",
+        (1_736_166_896, 123_456_789);
+        "positive offset"
+    )]
+    #[test_case::test_case("2025-01-06T11:34:56.5-01:00", (1_736_166_896, 500_000_000); "negative offset")]
+    #[test_case::test_case("2000-02-29T00:00:00Z", (951_782_400, 0); "leap day")]
+    fn parses_timestamp(timestamp: &str, expected: (i64, u32)) -> anyhow::Result<()> {
+        assert_eq!(parse_timestamp(timestamp)?, expected);
+        Ok(())
+    }
+
+    #[test_case::test_case(""; "empty")]
+    #[test_case::test_case("2025-01-06"; "date only")]
+    #[test_case::test_case("2025-01-06T12:34:56"; "no offset")]
+    #[test_case::test_case("2025-01-06T12:34:56.x1Z"; "invalid fraction")]
+    fn rejects_invalid_timestamp(timestamp: &str) {
+        assert!(parse_timestamp(timestamp).is_err())
+    }
+
+    fn image(id: &str, created: &str, repo_tags: Option<&[&str]>, project: Option<&str>) -> Image {
+        Image {
+            config: ImageConfig {
+                labels: project
+                    .map(|project| [("com.docker.compose.project".into(), project.into())].into()),
+            },
+            created: created.into(),
+            id: id.into(),
+            repo_tags: repo_tags
+                .map(|repo_tags| repo_tags.iter().map(|&repo_tag| repo_tag.into()).collect()),
+        }
+    }
+}
@@ -1,14 +1,52 @@
+use super::command;
 use super::log;
+use super::temp;
+use anyhow::Context;
+use std::env;
+use std::ffi;
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path;
 use std::process;
 
+// Exit code of SSH for its own errors, like failed host key verification, as
+// opposed to exit codes of remote commands.
+const ERROR_EXIT_CODE: i32 = 255;
+
+// Idle time in seconds after which a master connection ends by itself, in case
+// it is not closed explicitly.
+const CONTROL_PERSIST_SECONDS: u16 = 60;
+
 pub struct Cli<'a> {
     arguments: Arguments<'a>,
 }
 
 pub struct Arguments<'a> {
     pub config: Option<&'a str>,
+    pub control_folder: Option<&'a path::Path>,
     pub debug: bool,
+    pub identity: Option<&'a str>,
+    pub jump: Option<&'a str>,
+    pub known_hosts: &'a [path::PathBuf],
     pub log_level: Option<log::Level>,
+    pub options: &'a [String],
+    pub port: Option<u16>,
+}
+
+// Host keys pinned for strict host key checking, from known_hosts lines, which
+// are written to a private temporary file, and from a known_hosts file.
+pub struct KnownHosts {
+    files: Vec<path::PathBuf>,
+    _folder: Option<temp::Dir>,
+}
+
+// Shares one connection per host among all SSH commands run meanwhile,
+// including those that Docker runs for `ssh://` hosts, via control sockets in a
+// private temporary folder. The connections are closed on drop.
+pub struct Multiplexing {
+    folder: temp::Dir,
+    ssh_program: path::PathBuf,
 }
 
 impl<'a> Cli<'a> {
@@ -16,17 +54,41 @@ impl<'a> Cli<'a> {
         Self { arguments }
     }
 
+    // Like `dedicated_command` but reuses a shared connection if multiplexing.
     pub fn command(&self) -> process::Command {
+        let mut command = self.dedicated_command();
+        if let Some(folder) = self.arguments.control_folder {
+            command.args(control_options(folder));
+        }
+        command
+    }
+
+    // Opens a connection of its own, as needed for long-lived tunnels, which
+    // must outlive multiplexing.
+    pub fn dedicated_command(&self) -> process::Command {
         let mut command = process::Command::new("ssh");
 
         let Arguments {
             config,
+            control_folder: _,
             debug,
+            identity,
+            jump,
+            known_hosts,
             log_level,
+            options,
+            port,
         } = &self.arguments;
 
+        // SSH uses the first value obtained for each option, so explicit options
+        // come before any defaults of Kerek.
         command
             .args(config.iter().flat_map(|config| ["-F", config]))
+            .args(options.iter().flat_map(|option| ["-o", option]))
+            .args(identity.iter().flat_map(|identity| ["-i", identity]))
+            .args(jump.iter().flat_map(|jump| ["-J", jump]))
+            .args(port.iter().flat_map(|port| ["-p".into(), port.to_string()]))
+            .args(known_hosts_options(known_hosts).iter().flatten())
             .args(log_level.iter().flat_map(|log_level| {
                 ["-o".into(), {
                     let level = match log_level {
@@ -44,16 +106,235 @@ impl<'a> Cli<'a> {
     }
 }
 
+impl KnownHosts {
+    pub fn new(lines: &[String], file: Option<&str>) -> anyhow::Result<Self> {
+        // The file comes first as SSH records new host keys in the first file,
+        // say, when option `StrictHostKeyChecking=accept-new` is passed.
+        let mut files = file.iter().map(path::PathBuf::from).collect::<Vec<_>>();
+
+        let folder = if lines.is_empty() {
+            None
+        } else {
+            for line in lines {
+                anyhow::ensure!(
+                    line.split_whitespace().count() >= 3,
+                    "Expected known host like `<hostname> ssh-ed25519 AAAA…` but got {line:?}"
+                );
+            }
+
+            let folder = temp::Dir::new()?;
+            let path = folder.path().join("known_hosts");
+            fs::write(
+                &path,
+                lines
+                    .iter()
+                    .map(|line| format!("{line}\n"))
+                    .collect::<String>(),
+            )
+            .with_context(|| format!("Unable to write {path:?}"))?;
+            files.push(path);
+            Some(folder)
+        };
+
+        Ok(Self {
+            files,
+            _folder: folder,
+        })
+    }
+
+    pub fn files(&self) -> &[path::PathBuf] {
+        &self.files
+    }
+
+    // Explains an SSH error when pinning, as then SSH refuses any host whose
+    // key is not pinned, with the reason only shown in its own output.
+    pub fn explain_failure(&self, error: anyhow::Error) -> anyhow::Error {
+        let is_ssh_error = error.chain().any(|cause| {
+            cause
+                .downcast_ref::<command::ExitError>()
+                .is_some_and(|command::ExitError(status)| status.code() == Some(ERROR_EXIT_CODE))
+        });
+        if self.files.is_empty() || !is_ssh_error {
+            return error;
+        }
+        error.context(
+            "SSH failed with host keys pinned; check that keys of the host and of \
+             any jump hosts are pinned, with `[<hostname>]:<port>` for ports other \
+             than 22",
+        )
+    }
+}
+
+impl Multiplexing {
+    // Returns `None` if SSH is not installed, in which case there is nothing to
+    // share.
+    pub fn start() -> anyhow::Result<Option<Self>> {
+        let Some(ssh_program) = find_program("ssh") else {
+            log::debug!("Not multiplexing SSH connections as `ssh` is not found.");
+            return Ok(None);
+        };
+        let multiplexing = Self {
+            folder: temp::Dir::new()?,
+            ssh_program,
+        };
+        multiplexing.write_wrapper(&[])?;
+        Ok(Some(multiplexing))
+    }
+
+    // Folder of the control sockets and of the `ssh` wrapper for Docker.
+    pub fn folder(&self) -> &path::Path {
+        self.folder.path()
+    }
+
+    // Makes Docker connect to `ssh://` hosts with the arguments of the given
+    // CLI, like a custom SSH config file, which Docker has no option for.
+    pub fn apply_to_docker(&self, ssh_cli: &Cli) -> anyhow::Result<()> {
+        let command = ssh_cli.dedicated_command();
+        let arguments = command
+            .get_args()
+            .map(|argument| {
+                argument
+                    .to_str()
+                    .with_context(|| format!("SSH argument is not UTF-8: {argument:?}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.write_wrapper(&arguments)
+    }
+
+    // Docker runs `ssh` found via `PATH`, where this wrapper comes first. The
+    // wrapper runs the real `ssh` by the absolute path found at start.
+    fn write_wrapper(&self, arguments: &[&str]) -> anyhow::Result<()> {
+        let ssh_program = self
+            .ssh_program
+            .to_str()
+            .with_context(|| format!("SSH path is not UTF-8: {:?}", self.ssh_program))?;
+        let control_options = control_options(self.folder.path());
+        let options = arguments
+            .iter()
+            .copied()
+            .chain(control_options.iter().map(|option| option.as_str()))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let wrapper = self.folder.path().join("ssh");
+        fs::write(
+            &wrapper,
+            format!("#!/bin/sh\nexec {} {options} \"$@\"\n", quote(ssh_program)),
+        )
+        .with_context(|| format!("Unable to write {wrapper:?}"))?;
+        fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Unable to make {wrapper:?} executable"))
+    }
+}
+
+impl Drop for Multiplexing {
+    fn drop(&mut self) {
+        let entries = match fs::read_dir(self.folder.path()) {
+            Err(error) => {
+                log::error!("Error listing SSH control sockets: {error}");
+                return;
+            }
+            Ok(entries) => entries,
+        };
+
+        for entry in entries.flatten() {
+            if entry
+                .file_type()
+                .is_ok_and(|file_type| file_type.is_socket())
+            {
+                let socket = entry.path();
+                log::debug!("Closing SSH master connection of {socket:?}.");
+                let is_closed = process::Command::new(&self.ssh_program)
+                    .arg("-o")
+                    .arg(format!("ControlPath={}", socket.display()))
+                    .args(["-O", "exit", "kerek"])
+                    .stderr(process::Stdio::null())
+                    .status()
+                    .is_ok_and(|status| status.success());
+                if !is_closed {
+                    log::debug!("Unable to close SSH master connection of {socket:?}.");
+                }
+            }
+        }
+    }
+}
+
+// Returns `PATH` for Docker to run the `ssh` wrapper in given folder.
+pub fn wrapped_path(folder: &path::Path) -> Option<ffi::OsString> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::join_paths(std::iter::once(folder.into()).chain(env::split_paths(&path))).ok()
+}
+
+fn find_program(name: &str) -> Option<path::PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|folder| folder.join(name))
+        .find(|path| {
+            fs::metadata(path).is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
+
+fn control_options(folder: &path::Path) -> [String; 6] {
+    [
+        "-o".into(),
+        "ControlMaster=auto".into(),
+        "-o".into(),
+        format!("ControlPath={}", folder.join("%C").display()),
+        "-o".into(),
+        format!("ControlPersist={CONTROL_PERSIST_SECONDS}"),
+    ]
+}
+
+// Makes SSH accept only pinned host keys, failing with "Host key verification
+// failed" otherwise.
+fn known_hosts_options(known_hosts: &[path::PathBuf]) -> Option<[String; 6]> {
+    if known_hosts.is_empty() {
+        return None;
+    }
+
+    // SSH splits the value at spaces unless quoted, as in SSH config.
+    let files = known_hosts
+        .iter()
+        .map(|file| {
+            let file = file.display().to_string();
+            format!(r#""{}""#, file.replace('\\', r"\\").replace('"', r#"\""#))
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some([
+        "-o".into(),
+        format!("UserKnownHostsFile={files}"),
+        "-o".into(),
+        "GlobalKnownHostsFile=/dev/null".into(),
+        "-o".into(),
+        "StrictHostKeyChecking=yes".into(),
+    ])
+}
+
+// Quotes argument for a POSIX shell, as SSH passes the remote command to the
+// login shell of the remote user.
+pub fn quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', r"'\''"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::os::unix::process::ExitStatusExt;
 
     #[test]
     fn handles_minimum() -> anyhow::Result<()> {
         let command = Cli::new(Arguments {
             config: None,
+            control_folder: None,
             debug: false,
+            identity: None,
+            jump: None,
+            known_hosts: &[],
             log_level: None,
+            options: &[],
+            port: None,
         })
         .command();
 
@@ -66,16 +347,114 @@ mod tests {
     fn handles_maximum() -> anyhow::Result<()> {
         let command = Cli::new(Arguments {
             config: Some("config"),
+            control_folder: Some(path::Path::new("/tmp/kerek")),
             debug: true,
+            identity: Some("id_ed25519"),
+            jump: Some("bastion"),
+            known_hosts: &["known_hosts".into()],
             log_level: Some(log::Level::Warn),
+            options: &["StrictHostKeyChecking=accept-new".into()],
+            port: Some(2222),
         })
         .command();
 
         assert_eq!(command.get_program(), "ssh");
         assert_eq!(
             command.get_args().collect::<Vec<_>>(),
-            ["-F", "config", "-o", "LogLevel=ERROR", "-vvv"],
+            [
+                "-F",
+                "config",
+                "-o",
+                "StrictHostKeyChecking=accept-new",
+                "-i",
+                "id_ed25519",
+                "-J",
+                "bastion",
+                "-p",
+                "2222",
+                "-o",
+                r#"UserKnownHostsFile="known_hosts""#,
+                "-o",
+                "GlobalKnownHostsFile=/dev/null",
+                "-o",
+                "StrictHostKeyChecking=yes",
+                "-o",
+                "LogLevel=ERROR",
+                "-vvv",
+                "-o",
+                "ControlMaster=auto",
+                "-o",
+                "ControlPath=/tmp/kerek/%C",
+                "-o",
+                "ControlPersist=60",
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gets_control_options() {
+        assert_eq!(
+            control_options(path::Path::new("/tmp/kerek")),
+            [
+                "-o",
+                "ControlMaster=auto",
+                "-o",
+                "ControlPath=/tmp/kerek/%C",
+                "-o",
+                "ControlPersist=60",
+            ],
+        )
+    }
+
+    #[test]
+    fn handles_known_hosts() -> anyhow::Result<()> {
+        let known_hosts =
+            KnownHosts::new(&["my-host ssh-ed25519 AAAA".into()], Some("my_known_hosts"))?;
+
+        assert_eq!(known_hosts.files().len(), 2);
+        assert_eq!(known_hosts.files()[0], path::Path::new("my_known_hosts"));
+        assert_eq!(
+            fs::read_to_string(&known_hosts.files()[1])?,
+            "my-host ssh-ed25519 AAAA\n",
         );
         Ok(())
     }
+
+    #[test]
+    fn quotes_known_hosts_files() {
+        assert_eq!(
+            known_hosts_options(&["my known_hosts".into(), r#"a\"b"#.into()])
+                .map(|options| options[1].clone()),
+            Some(r#"UserKnownHostsFile="my known_hosts" "a\\\"b""#.into()),
+        )
+    }
+
+    #[test_case::test_case(true, 255, true; "pinned SSH error")]
+    #[test_case::test_case(true, 1, false; "pinned other error")]
+    #[test_case::test_case(false, 255, false; "not pinned")]
+    fn explains_failure(is_pinned: bool, exit_code: i32, expected: bool) -> anyhow::Result<()> {
+        let known_hosts = KnownHosts::new(&[], is_pinned.then_some("known_hosts"))?;
+        let error = anyhow::Error::new(command::ExitError(process::ExitStatus::from_raw(
+            exit_code << 8,
+        )))
+        .context("Error with command: ssh");
+
+        let message = known_hosts.explain_failure(error).to_string();
+
+        assert_eq!(message.contains("host keys pinned"), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_known_host() {
+        assert!(KnownHosts::new(&["my-host".into()], None).is_err())
+    }
+
+    #[test_case::test_case("", "''"; "empty")]
+    #[test_case::test_case("a b", "'a b'"; "space")]
+    #[test_case::test_case("it's", r"'it'\''s'"; "single quote")]
+    fn quotes(argument: &str, expected: &str) {
+        assert_eq!(quote(argument), expected);
+    }
 }
@@ -0,0 +1,196 @@
+use super::deploy;
+use super::deploy::model;
+use super::docker;
+use super::docker_compose;
+use std::collections;
+
+pub fn go(
+    In {
+        docker_cli,
+        docker_compose_cli,
+        service_names,
+    }: In,
+) -> anyhow::Result<()> {
+    deploy::probe(&docker_cli, &docker_compose_cli)?;
+    let (actual_containers, desired_services) = deploy::get_state(
+        &service_names,
+        &docker_cli,
+        &docker_compose_cli,
+        !service_names.is_empty(),
+    )?;
+
+    print!("{}", render(&actual_containers, &desired_services));
+    Ok(())
+}
+
+pub struct In<'a> {
+    pub docker_cli: docker::Cli<'a>,
+    pub docker_compose_cli: docker_compose::Cli<'a>,
+    pub service_names: collections::BTreeSet<String>,
+}
+
+fn render(
+    actual_containers: &model::ActualContainers,
+    desired_services: &model::DesiredServices,
+) -> String {
+    let service_names = actual_containers
+        .iter()
+        .map(|container| &container.service_name)
+        .chain(desired_services.keys())
+        .collect::<collections::BTreeSet<_>>();
+
+    let header = [
+        "SERVICE",
+        "DESIRED",
+        "RUNNING",
+        "UP-TO-DATE",
+        "STALE",
+        "HEALTH",
+        "UPDATE ORDER",
+    ]
+    .map(String::from);
+
+    let rows = service_names.into_iter().map(|service_name| {
+        let desired_service = desired_services.get(service_name);
+        let containers = actual_containers
+            .iter()
+            .filter(|container| &container.service_name == service_name)
+            .collect::<Vec<_>>();
+        let up_to_date_count = containers
+            .iter()
+            .filter(|container| {
+                desired_service.is_some_and(|desired_service| {
+                    container.service_config_hash == desired_service.service_config_hash
+                })
+            })
+            .count();
+
+        [
+            service_name.clone(),
+            desired_service
+                .map_or(0, |service| service.replica_count)
+                .to_string(),
+            containers
+                .iter()
+                .filter(|container| container.is_running)
+                .count()
+                .to_string(),
+            up_to_date_count.to_string(),
+            (containers.len() - up_to_date_count).to_string(),
+            summarize_health(&containers),
+            match desired_service.map(|service| &service.update_order) {
+                None => "–".into(),
+                Some(model::OperationOrder::StartFirst) => "start-first".into(),
+                Some(model::OperationOrder::StopFirst) => "stop-first".into(),
+            },
+        ]
+    });
+
+    let table = [header].into_iter().chain(rows).collect::<Vec<_>>();
+    let column_widths = (0..table[0].len())
+        .map(|column| {
+            table
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    table
+        .iter()
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(&column_widths)
+                .map(|(field, width)| format!("{field:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            format!("{}\n", line.trim_end())
+        })
+        .collect()
+}
+
+fn summarize_health(containers: &[&model::ActualContainer]) -> String {
+    let mut health_counts = collections::BTreeMap::<_, usize>::new();
+
+    for health in containers
+        .iter()
+        .filter_map(|container| container.health.as_ref())
+    {
+        *health_counts.entry(health).or_default() += 1;
+    }
+
+    if health_counts.is_empty() {
+        "–".into()
+    } else {
+        health_counts
+            .into_iter()
+            .map(|(health, count)| format!("{count} {health}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_handles() {
+        let container =
+            |container_id: &str, service_name: &str, hash: &str| model::ActualContainer {
+                container_id: container_id.into(),
+                health: None,
+                image_id: "i".into(),
+                is_running: true,
+                service_config_hash: hash.into(),
+                service_name: service_name.into(),
+            };
+        let actual_containers = [
+            model::ActualContainer {
+                health: Some("healthy".into()),
+                ..container("0", "greet", "a")
+            },
+            model::ActualContainer {
+                health: Some("starting".into()),
+                ..container("1", "greet", "b")
+            },
+            model::ActualContainer {
+                is_running: false,
+                ..container("2", "orphan", "c")
+            },
+            container("3", "reverse-proxy", "d"),
+        ]
+        .into();
+        let desired_services = [
+            (
+                "greet".into(),
+                model::DesiredServiceDefinition {
+                    replica_count: 2,
+                    service_config_hash: "b".into(),
+                    update_order: model::OperationOrder::StartFirst,
+                },
+            ),
+            (
+                "reverse-proxy".into(),
+                model::DesiredServiceDefinition {
+                    replica_count: 1,
+                    service_config_hash: "d".into(),
+                    update_order: model::OperationOrder::StopFirst,
+                },
+            ),
+        ]
+        .into();
+
+        assert_eq!(
+            render(&actual_containers, &desired_services),
+            "\
+SERVICE        DESIRED  RUNNING  UP-TO-DATE  STALE  HEALTH                 UPDATE ORDER
+greet          2        2        1           1      1 healthy, 1 starting  start-first
+orphan         0        0        0           1      –                      –
+reverse-proxy  1        1        1           0      –                      stop-first
+",
+        );
+    }
+}
@@ -0,0 +1,478 @@
+use anyhow::Context;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::ops;
+
+const BLOCK_SIZE: u64 = 512;
+// Limits extended headers held in memory, as archives may come from anyone.
+const MAX_METADATA_SIZE: u64 = 1 << 20;
+
+// Entry of a tar archive, including any extended headers that precede it.
+pub struct Entry {
+    pub data: ops::Range<u64>,
+    pub path: String,
+    pub range: ops::Range<u64>,
+}
+
+pub fn read_entries<T: Read + Seek>(archive: &mut T) -> anyhow::Result<Vec<Entry>> {
+    let mut entries = vec![];
+    let mut offset = archive.stream_position()?;
+    let mut entry_start = offset;
+    let mut path_override = None;
+
+    loop {
+        let mut header = [0; BLOCK_SIZE as usize];
+        archive
+            .read_exact(&mut header)
+            .context("Unable to read tar header")?;
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        let size = parse_size(&header[124..136])?;
+        let data = offset + BLOCK_SIZE..offset + BLOCK_SIZE + size;
+        offset = data.start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+
+        if is_metadata(header[156]) {
+            read_metadata(archive, header[156], size, &mut path_override)?;
+        } else {
+            check_entry_type(header[156])?;
+            let path = path_override.take().unwrap_or_else(|| parse_path(&header));
+            entries.push(Entry {
+                data,
+                path,
+                range: entry_start..offset,
+            });
+            entry_start = offset;
+        }
+
+        archive.seek(io::SeekFrom::Start(offset))?;
+    }
+
+    Ok(entries)
+}
+
+// Reads an archive front to back without seeking, for example when streamed
+// from a decompressor, visiting the data of each regular entry.
+pub fn read_stream<T: Read>(
+    archive: &mut T,
+    mut visit: impl FnMut(&str, &mut dyn Read) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut path_override = None;
+
+    loop {
+        let mut header = [0; BLOCK_SIZE as usize];
+        archive
+            .read_exact(&mut header)
+            .context("Unable to read tar header")?;
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        let size = parse_size(&header[124..136])?;
+        let mut data = (&mut *archive).take(size);
+
+        if is_metadata(header[156]) {
+            read_metadata(&mut data, header[156], size, &mut path_override)?;
+        } else {
+            check_entry_type(header[156])?;
+            let path = path_override.take().unwrap_or_else(|| parse_path(&header));
+            visit(&path, &mut data)?;
+        }
+
+        // Skip any data left unread by visitor as well as padding.
+        let padding = size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE - size;
+        io::copy(&mut data, &mut io::sink())?;
+        io::copy(&mut (&mut *archive).take(padding), &mut io::sink())?;
+    }
+
+    Ok(())
+}
+
+pub fn read_data<T: Read + Seek>(archive: &mut T, entry: &Entry) -> anyhow::Result<Vec<u8>> {
+    let mut data = vec![0; (entry.data.end - entry.data.start).try_into()?];
+    archive.seek(io::SeekFrom::Start(entry.data.start))?;
+    archive
+        .read_exact(&mut data)
+        .with_context(|| format!("Unable to read tar entry {:?}", entry.path))?;
+    Ok(data)
+}
+
+// Copies given entries of an archive as is, then ends the new archive.
+pub fn write_entries<'a, T: Read + Seek, U: IntoIterator<Item = &'a Entry>>(
+    archive: &mut T,
+    entries: U,
+    destination: &mut impl Write,
+) -> anyhow::Result<u64> {
+    let mut size = 0;
+
+    for entry in entries {
+        archive.seek(io::SeekFrom::Start(entry.range.start))?;
+        size += io::copy(
+            &mut archive.take(entry.range.end - entry.range.start),
+            destination,
+        )?;
+    }
+
+    Ok(size + write_end(destination)?)
+}
+
+// Appends a regular file with given data, which must be exactly `size` bytes.
+// Long paths are stored in a preceding pax extended header.
+pub fn write_file(
+    destination: &mut impl Write,
+    path: &str,
+    size: u64,
+    data: &mut impl Read,
+) -> anyhow::Result<()> {
+    let name = if path.len() < 100 {
+        path
+    } else {
+        let record = pax_record("path", path);
+        write_header(destination, "././@PaxHeader", record.len() as u64, b'x')?;
+        write_data(
+            destination,
+            "././@PaxHeader",
+            record.len() as u64,
+            &mut record.as_bytes(),
+        )?;
+        let end = (0..100)
+            .rev()
+            .find(|&end| path.is_char_boundary(end))
+            .unwrap_or(0);
+        &path[..end]
+    };
+
+    write_header(destination, name, size, b'0')?;
+    write_data(destination, path, size, data)
+}
+
+pub fn write_end(destination: &mut impl Write) -> io::Result<u64> {
+    let end = [0; 2 * BLOCK_SIZE as usize];
+    destination.write_all(&end)?;
+    Ok(end.len() as u64)
+}
+
+fn write_header(
+    destination: &mut impl Write,
+    name: &str,
+    size: u64,
+    type_flag: u8,
+) -> anyhow::Result<()> {
+    let mut header = [0; BLOCK_SIZE as usize];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    write_size(&mut header[124..136], size);
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = type_flag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&byte| u32::from(byte)).sum::<u32>();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    destination.write_all(&header)?;
+    Ok(())
+}
+
+fn write_data(
+    destination: &mut impl Write,
+    path: &str,
+    size: u64,
+    data: &mut impl Read,
+) -> anyhow::Result<()> {
+    let copied_size = io::copy(&mut data.take(size), destination)?;
+    anyhow::ensure!(
+        copied_size == size,
+        "Tar entry {path:?} has {copied_size} instead of {size} bytes"
+    );
+    let padding = size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE - size;
+    destination.write_all(&vec![0; padding.try_into()?])?;
+    Ok(())
+}
+
+// Pax record like "19 path=a/long/path\n", whose length counts itself.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {key}={value}\n");
+    let mut length = rest.len();
+    while length != length.to_string().len() + rest.len() {
+        length = length.to_string().len() + rest.len();
+    }
+    format!("{length}{rest}")
+}
+
+// Extended headers for the next entry: pax global and per-entry headers, and
+// GNU long name and long link name headers.
+fn is_metadata(type_flag: u8) -> bool {
+    matches!(type_flag, b'g' | b'x' | b'L' | b'K')
+}
+
+fn read_metadata(
+    archive: &mut impl Read,
+    type_flag: u8,
+    size: u64,
+    path_override: &mut Option<String>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        size <= MAX_METADATA_SIZE,
+        "Tar extended header has {size} bytes, more than the limit of {MAX_METADATA_SIZE}"
+    );
+    let mut metadata = vec![];
+    archive.take(size).read_to_end(&mut metadata)?;
+    anyhow::ensure!(
+        metadata.len() as u64 == size,
+        "Tar extended header is truncated"
+    );
+
+    match type_flag {
+        b'x' => *path_override = parse_pax_path(&metadata).or(path_override.take()),
+        b'L' => *path_override = Some(parse_string(&metadata)),
+        _ => {}
+    }
+    Ok(())
+}
+
+// Regular files, links, devices, folders, and FIFOs as in POSIX, but no GNU
+// extensions like sparse files, whose data would be misread.
+fn check_entry_type(type_flag: u8) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        matches!(type_flag, b'\0' | b'0'..=b'7'),
+        "Unsupported tar entry type {:?}",
+        char::from(type_flag)
+    );
+    Ok(())
+}
+
+fn parse_size(field: &[u8]) -> anyhow::Result<u64> {
+    // Base-256 encoding for large sizes, otherwise octal.
+    if field[0] & 0x80 != 0 {
+        Ok(field[1..]
+            .iter()
+            .fold(0, |size, &byte| (size << 8) | u64::from(byte)))
+    } else {
+        let size = parse_string(field);
+        let size = size.trim();
+        if size.is_empty() {
+            Ok(0)
+        } else {
+            u64::from_str_radix(size, 8).with_context(|| format!("Invalid tar size {size:?}"))
+        }
+    }
+}
+
+fn write_size(field: &mut [u8], size: u64) {
+    // Octal with 11 digits fits sizes below 8 GiB, base-256 encoding beyond.
+    if size < 1 << 33 {
+        field.copy_from_slice(format!("{size:011o}\0").as_bytes());
+    } else {
+        field.fill(0);
+        field[0] = 0x80;
+        let bytes = size.to_be_bytes();
+        let start = field.len() - bytes.len();
+        field[start..].copy_from_slice(&bytes);
+    }
+}
+
+fn parse_path(header: &[u8]) -> String {
+    let name = parse_string(&header[0..100]);
+    let prefix = parse_string(&header[345..500]);
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+fn parse_pax_path(metadata: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(metadata)
+        .lines()
+        .find_map(|record| {
+            let (_, key_value) = record.split_once(' ')?;
+            key_value.strip_prefix("path=").map(|path| path.into())
+        })
+}
+
+fn parse_string(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn handles() -> anyhow::Result<()> {
+        let mut archive = io::Cursor::new(archive(&[
+            ("a", "Hi"),
+            (&"b".repeat(150), "there"),
+            ("c", ""),
+        ])?);
+
+        let entries = read_entries(&mut archive)?;
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect::<Vec<_>>(),
+            ["a", &"b".repeat(150), "c"],
+        );
+        assert_eq!(read_data(&mut archive, &entries[1])?, b"there");
+
+        let mut subset = vec![];
+        write_entries(&mut archive, [&entries[0], &entries[2]], &mut subset)?;
+        let subset_entries = read_entries(&mut io::Cursor::new(&subset))?;
+        assert_eq!(
+            subset_entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "c"],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn streams() -> anyhow::Result<()> {
+        let archive = archive(&[("a", "Hi"), (&"b".repeat(150), "there"), ("c", "")])?;
+
+        assert_eq!(
+            stream(&archive)?,
+            [
+                ("a".into(), "Hi".into()),
+                ("b".repeat(150), "there".into()),
+                ("c".into(), "".into()),
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes() -> anyhow::Result<()> {
+        let mut archive = vec![];
+        write_file(&mut archive, "a", 2, &mut "Hi".as_bytes())?;
+        write_file(&mut archive, "b/c", 0, &mut io::empty())?;
+        write_end(&mut archive)?;
+
+        assert_eq!(
+            stream(&archive)?,
+            [("a".into(), "Hi".into()), ("b/c".into(), "".into())],
+        );
+
+        let folder = temp::Dir::new()?;
+        fs::write(folder.path().join("archive.tar"), &archive)?;
+        let output = process::Command::new("tar")
+            .args(["--list", "--file"])
+            .arg(folder.path().join("archive.tar"))
+            .output()?;
+        anyhow::ensure!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8(output.stdout)?, "a\nb/c\n");
+        Ok(())
+    }
+
+    #[test]
+    fn writes_long_path() -> anyhow::Result<()> {
+        let path = format!("{}/c", "b".repeat(150));
+        let mut archive = vec![];
+        write_file(&mut archive, &path, 2, &mut "Hi".as_bytes())?;
+        write_end(&mut archive)?;
+
+        assert_eq!(stream(&archive)?, [(path.clone(), "Hi".into())]);
+
+        let folder = temp::Dir::new()?;
+        fs::write(folder.path().join("archive.tar"), &archive)?;
+        let output = process::Command::new("tar")
+            .args(["--list", "--file"])
+            .arg(folder.path().join("archive.tar"))
+            .output()?;
+        anyhow::ensure!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8(output.stdout)?, format!("{path}\n"));
+        Ok(())
+    }
+
+    #[test_case::test_case("", "8 path=\n"; "empty")]
+    #[test_case::test_case("a", "9 path=a\n"; "short")]
+    #[test_case::test_case(&"a".repeat(91), &format!("101 path={}\n", "a".repeat(91)); "extra digit")]
+    fn makes_pax_record(value: &str, expected: &str) {
+        assert_eq!(pax_record("path", value), expected);
+    }
+
+    #[test]
+    fn skips_long_link_name() -> anyhow::Result<()> {
+        let mut archive = vec![];
+        write_header(&mut archive, "././@LongLink", 151, b'K')?;
+        write_data(&mut archive, "", 151, &mut "c".repeat(151).as_bytes())?;
+        write_header(&mut archive, "a", 0, b'2')?;
+        write_file(&mut archive, "b", 2, &mut "Hi".as_bytes())?;
+        write_end(&mut archive)?;
+
+        assert_eq!(
+            stream(&archive)?,
+            [("a".into(), "".into()), ("b".into(), "Hi".into())],
+        );
+        assert_eq!(read_entries(&mut io::Cursor::new(&archive))?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_large_metadata() -> anyhow::Result<()> {
+        let mut archive = vec![];
+        write_header(&mut archive, "././@PaxHeader", MAX_METADATA_SIZE + 1, b'x')?;
+
+        assert!(stream(&archive).is_err());
+        assert!(read_entries(&mut io::Cursor::new(&archive)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unsupported_entry_type() -> anyhow::Result<()> {
+        let mut archive = vec![];
+        write_header(&mut archive, "a", 0, b'S')?;
+        write_end(&mut archive)?;
+
+        assert!(stream(&archive).is_err());
+        assert!(read_entries(&mut io::Cursor::new(&archive)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_wrong_size() {
+        assert!(write_file(&mut vec![], "a", 3, &mut "Hi".as_bytes()).is_err());
+    }
+
+    fn stream(archive: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+        let mut files = vec![];
+        read_stream(&mut &archive[..], |path, data| {
+            let mut contents = String::new();
+            data.read_to_string(&mut contents)?;
+            files.push((path.into(), contents));
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    fn archive(files: &[(&str, &str)]) -> anyhow::Result<Vec<u8>> {
+        let folder = temp::Dir::new()?;
+        for (path, contents) in files {
+            fs::write(folder.path().join(path), contents)?;
+        }
+        let output = process::Command::new("tar")
+            .args(["--create", "--format", "posix", "--directory"])
+            .arg(folder.path())
+            .args(files.iter().map(|(path, _)| path))
+            .output()?;
+        anyhow::ensure!(output.status.success(), "{output:?}");
+        Ok(output.stdout)
+    }
+}
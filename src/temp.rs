@@ -0,0 +1,69 @@
+use super::log;
+use anyhow::Context;
+use std::env;
+use std::ffi;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path;
+
+// Private temporary folder, removed with all its contents on drop.
+pub struct Dir {
+    path: path::PathBuf,
+}
+
+impl Dir {
+    // Creates the folder with a random name as `mktemp -d` does, so folders
+    // left behind or created by other users do not get in the way.
+    pub fn new() -> anyhow::Result<Self> {
+        let template = env::temp_dir().join("kerek-XXXXXX");
+        let mut template = ffi::CString::new(template.as_os_str().as_bytes())
+            .with_context(|| format!("Invalid temporary folder {template:?}"))?
+            .into_bytes_with_nul();
+
+        // SAFETY: The template is a NUL-terminated buffer that `mkdtemp` only
+        // modifies in place.
+        let result = unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) };
+        template.pop();
+        let path = path::PathBuf::from(ffi::OsString::from_vec(template));
+        if result.is_null() {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("Unable to create temporary folder {path:?}"));
+        }
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let path = &self.path;
+        if let Err(error) = fs::remove_dir_all(path) {
+            log::error!("Error removing temporary folder {path:?}: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn creates_private_folders() -> anyhow::Result<()> {
+        let folder = Dir::new()?;
+        let other_folder = Dir::new()?;
+        let path = folder.path().to_path_buf();
+
+        assert_ne!(folder.path(), other_folder.path());
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o700);
+        drop(folder);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::command;
+use crate::docker_compose;
+use std::collections;
+
+#[derive(serde::Deserialize)]
+struct ComposeAppDefinition {
+    name: String,
+    services: collections::BTreeMap<String, ServiceDefinition>,
+}
+
+#[derive(serde::Deserialize)]
+struct ServiceDefinition {
+    build: Option<serde_json::Value>,
+    image: Option<String>,
+}
+
+// Compose project with the images of its services.
+pub struct Project {
+    pub images: Vec<String>,
+    pub name: String,
+}
+
+pub fn get_images(
+    docker_compose_cli: &docker_compose::Cli,
+    service_names: &[String],
+) -> anyhow::Result<Vec<String>> {
+    Ok(get_project(docker_compose_cli, service_names)?.images)
+}
+
+pub fn get_project(
+    docker_compose_cli: &docker_compose::Cli,
+    service_names: &[String],
+) -> anyhow::Result<Project> {
+    let compose_app_definition = command::stdout_json(
+        docker_compose_cli
+            .command()
+            .args(["config", "--format", "json", "--"])
+            .args(service_names),
+    )?;
+    Ok(convert_compose_app_definition(compose_app_definition))
+}
+
+fn convert_compose_app_definition(
+    ComposeAppDefinition { name, services }: ComposeAppDefinition,
+) -> Project {
+    let mut images = services
+        .into_iter()
+        .filter_map(|(service_name, ServiceDefinition { build, image })| {
+            // Compose names images of buildable services like this by default.
+            let image = image.or_else(|| build.map(|_| format!("{name}-{service_name}")))?;
+            Some(with_default_tag(image))
+        })
+        .collect::<Vec<_>>();
+    images.sort();
+    images.dedup();
+    Project { images, name }
+}
+
+fn with_default_tag(image: String) -> String {
+    let name = image.rsplit('/').next().unwrap_or_default();
+    if image.contains('@') || name.contains(':') {
+        image
+    } else {
+        format!("{image}:latest")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles() -> anyhow::Result<()> {
+        let compose_app_definition = serde_json::from_value(serde_json::json!({
+            "name": "my-project",
+            "services": {
+                "a": {"image": "a:1"},
+                "b": {"build": {"context": "."}},
+                "c": {"build": {"context": "."}, "image": "c"},
+                "d": {"image": "localhost:5000/d"},
+                "e": {"image": "e@sha256:0123"},
+                "f": {"image": "a:1"},
+            },
+        }))?;
+
+        let Project { images, name } = convert_compose_app_definition(compose_app_definition);
+
+        assert_eq!(name, "my-project");
+        assert_eq!(
+            images,
+            [
+                "a:1",
+                "c:latest",
+                "e@sha256:0123",
+                "localhost:5000/d:latest",
+                "my-project-b:latest",
+            ],
+        );
+        Ok(())
+    }
+}
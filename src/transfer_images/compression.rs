@@ -0,0 +1,211 @@
+use crate::log;
+use std::io;
+use std::process;
+use std::sync;
+use std::sync::atomic;
+use std::time;
+
+pub enum Compression {
+    Builtin(Builtin),
+    Command(Vec<String>),
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Builtin {
+    codec: Codec,
+    level: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Codec {
+    Gzip,
+    Zstd,
+}
+
+// Counts bytes read before and after compression to report statistics.
+#[derive(Default)]
+pub struct Stats {
+    input_size: atomic::AtomicU64,
+    output_size: atomic::AtomicU64,
+    start: sync::OnceLock<time::Instant>,
+}
+
+struct CountingReader<T> {
+    is_input: bool,
+    reader: T,
+    stats: sync::Arc<Stats>,
+}
+
+impl Compression {
+    pub fn parse(command: Vec<String>) -> anyhow::Result<Self> {
+        match &command[..] {
+            [] => Ok(Self::None),
+            [argument] if argument.starts_with("builtin:") => {
+                Builtin::parse(argument).map(Self::Builtin)
+            }
+            _ => Ok(Self::Command(command)),
+        }
+    }
+
+    pub fn arguments(&self) -> &[String] {
+        match self {
+            Self::Command(command) => command,
+            Self::Builtin(_) | Self::None => &[],
+        }
+    }
+
+    pub fn command(&self) -> Option<process::Command> {
+        self.arguments().split_first().map(|(program, arguments)| {
+            let mut command = process::Command::new(program);
+            command.args(arguments);
+            command
+        })
+    }
+}
+
+impl Builtin {
+    fn parse(argument: &str) -> anyhow::Result<Self> {
+        let mut parts = argument.split(':').skip(1);
+        let codec = match parts.next() {
+            Some("gzip") => Codec::Gzip,
+            Some("zstd") => Codec::Zstd,
+            _ => anyhow::bail!(
+                "Unknown built-in compression {argument:?}, \
+                expected `builtin:gzip[:<level>]` or `builtin:zstd[:<level>]`"
+            ),
+        };
+        let levels = match codec {
+            Codec::Gzip => 0..=9,
+            Codec::Zstd => 1..=22,
+        };
+        let level = match parts.next() {
+            None => match codec {
+                Codec::Gzip => 6,
+                Codec::Zstd => zstd::DEFAULT_COMPRESSION_LEVEL,
+            },
+            Some(level) => level
+                .parse()
+                .ok()
+                .filter(|level| levels.contains(level))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid level {level:?} in {argument:?}, expected one of {levels:?}"
+                    )
+                })?,
+        };
+        anyhow::ensure!(
+            parts.next().is_none(),
+            "Invalid built-in compression {argument:?}"
+        );
+        Ok(Self { codec, level })
+    }
+
+    pub fn encode<'a, T: io::Read + Send + 'a>(
+        self,
+        reader: T,
+        stats: sync::Arc<Stats>,
+    ) -> io::Result<Box<dyn io::Read + Send + 'a>> {
+        let reader = CountingReader {
+            is_input: true,
+            reader,
+            stats: stats.clone(),
+        };
+        let encoder: Box<dyn io::Read + Send + 'a> = match self.codec {
+            Codec::Gzip => Box::new(flate2::read::GzEncoder::new(
+                reader,
+                flate2::Compression::new(self.level.unsigned_abs()),
+            )),
+            Codec::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, self.level)?),
+        };
+        Ok(Box::new(CountingReader {
+            is_input: false,
+            reader: encoder,
+            stats,
+        }))
+    }
+}
+
+impl Stats {
+    pub fn log(&self, image: &str) {
+        let input_size = self.input_size.load(atomic::Ordering::Relaxed);
+        let output_size = self.output_size.load(atomic::Ordering::Relaxed);
+        let seconds = self
+            .start
+            .get()
+            .map(|start| start.elapsed().as_secs_f64())
+            .unwrap_or_default();
+        let ratio = input_size as f64 / output_size.max(1) as f64;
+        let throughput = input_size as f64 / seconds.max(f64::EPSILON) / 1e6;
+        log::info!(
+            "Compressed image {image:?} from {input_size} to {output_size} bytes \
+            (ratio {ratio:.2}) at {throughput:.1} MB/s."
+        );
+    }
+}
+
+impl<T: io::Read> io::Read for CountingReader<T> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.stats.start.get_or_init(time::Instant::now);
+        let count = self.reader.read(buffer)?;
+        let size = if self.is_input {
+            &self.stats.input_size
+        } else {
+            &self.stats.output_size
+        };
+        size.fetch_add(count as u64, atomic::Ordering::Relaxed);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test_case::test_case("builtin:gzip", Codec::Gzip, 6; "gzip default")]
+    #[test_case::test_case("builtin:gzip:9", Codec::Gzip, 9; "gzip level")]
+    #[test_case::test_case("builtin:zstd", Codec::Zstd, 3; "zstd default")]
+    #[test_case::test_case("builtin:zstd:19", Codec::Zstd, 19; "zstd level")]
+    fn parses_builtin(argument: &str, codec: Codec, level: i32) -> anyhow::Result<()> {
+        assert_eq!(Builtin::parse(argument)?, Builtin { codec, level });
+        Ok(())
+    }
+
+    #[test_case::test_case("builtin:xz"; "unknown codec")]
+    #[test_case::test_case("builtin:gzip:10"; "level too high")]
+    #[test_case::test_case("builtin:zstd:fast"; "invalid level")]
+    #[test_case::test_case("builtin:zstd:3:4"; "too many parts")]
+    fn rejects_builtin(argument: &str) {
+        assert!(Builtin::parse(argument).is_err());
+    }
+
+    #[test_case::test_case("builtin:gzip"; "gzip")]
+    #[test_case::test_case("builtin:zstd"; "zstd")]
+    fn encodes(argument: &str) -> anyhow::Result<()> {
+        let input = "Hi there. ".repeat(1000);
+        let stats = sync::Arc::new(Stats::default());
+        let mut output = vec![];
+        Builtin::parse(argument)?
+            .encode(input.as_bytes(), stats.clone())?
+            .read_to_end(&mut output)?;
+
+        let mut decoded = String::new();
+        match Builtin::parse(argument)?.codec {
+            Codec::Gzip => {
+                flate2::read::GzDecoder::new(&output[..]).read_to_string(&mut decoded)?
+            }
+            Codec::Zstd => {
+                zstd::stream::read::Decoder::new(&output[..])?.read_to_string(&mut decoded)?
+            }
+        };
+
+        assert_eq!(decoded, input);
+        assert_eq!(stats.input_size.load(atomic::Ordering::Relaxed), 10000);
+        assert_eq!(
+            stats.output_size.load(atomic::Ordering::Relaxed),
+            output.len() as u64,
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,369 @@
+use crate::command;
+use crate::docker;
+use crate::tar;
+use anyhow::Context;
+use std::collections::hash_set;
+use std::fs;
+use std::io;
+use std::path;
+
+// Image archive as by `docker save`, with layers identified by their chain of
+// layer digests from the base layer up to the layer itself. A layer is only
+// reusable on a destination that has the same chain, not just the same digest.
+pub struct Archive {
+    entries: Vec<tar::Entry>,
+    file: fs::File,
+    layers: Vec<Layer>,
+}
+
+pub struct Delta {
+    pub layer_count: usize,
+    pub sent_layer_count: usize,
+}
+
+struct Layer {
+    chain: String,
+    path: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Manifest {
+    config: String,
+    layers: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Config {
+    rootfs: RootFs,
+}
+
+#[derive(serde::Deserialize)]
+struct RootFs {
+    diff_ids: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Image {
+    #[serde(rename = "RootFS")]
+    root_fs: ImageRootFs,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageRootFs {
+    #[serde(default)]
+    layers: Vec<String>,
+}
+
+// Loading an archive without some layer blobs only works with the classic image
+// store of Docker Engine, not with the containerd image store or with Podman.
+pub fn is_supported(docker_cli: &docker::Cli) -> anyhow::Result<bool> {
+    let engine = docker_cli.probe(None)?;
+    if engine.kind != docker::EngineKind::Docker {
+        return Ok(false);
+    }
+    let driver_status = command::stdout_json(docker_cli.command().args([
+        "info",
+        "--format",
+        "{{json .DriverStatus}}",
+    ]))?;
+    Ok(!uses_containerd_store(driver_status))
+}
+
+pub fn get_layer_chains(docker_cli: &docker::Cli) -> anyhow::Result<hash_set::HashSet<String>> {
+    let image_ids = command::stdout_utf8(docker_cli.command().args([
+        "images",
+        "--all",
+        "--no-trunc",
+        "--quiet",
+    ]))?;
+    let image_ids = image_ids
+        .lines()
+        .collect::<hash_set::HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let images = if image_ids.is_empty() {
+        vec![]
+    } else {
+        command::stdout_json::<Vec<Image>>(
+            docker_cli
+                .command()
+                .args(["image", "inspect", "--"])
+                .args(image_ids),
+        )?
+    };
+
+    Ok(get_chains(&images))
+}
+
+impl Archive {
+    pub fn open(path: &path::Path) -> anyhow::Result<Self> {
+        let mut file = fs::File::open(path)?;
+        let entries = tar::read_entries(&mut file)?;
+
+        let find_data = |file: &mut fs::File, path: &str| {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.path == path)
+                .with_context(|| format!("No {path:?} in image archive"))?;
+            tar::read_data(file, entry)
+        };
+
+        let manifests =
+            serde_json::from_slice::<Vec<Manifest>>(&find_data(&mut file, "manifest.json")?)
+                .context("Unable to parse image archive manifest")?;
+
+        let mut layers = vec![];
+        for Manifest {
+            config,
+            layers: layer_paths,
+        } in manifests
+        {
+            let Config {
+                rootfs: RootFs { diff_ids },
+            } = serde_json::from_slice(&find_data(&mut file, &config)?)
+                .with_context(|| format!("Unable to parse image config {config:?}"))?;
+
+            anyhow::ensure!(
+                diff_ids.len() == layer_paths.len(),
+                "Image config {config:?} does not match layers of manifest",
+            );
+
+            layers.extend(
+                layer_paths
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, path)| Layer {
+                        chain: chain(&diff_ids[..=index]),
+                        path,
+                    }),
+            );
+        }
+
+        Ok(Self {
+            entries,
+            file,
+            layers,
+        })
+    }
+
+    // Writes an archive without the layers that a destination with given layer
+    // chains already has. Such an archive is still accepted by `docker load`
+    // as existing layers are not read again.
+    pub fn write_delta(
+        &mut self,
+        destination_layer_chains: &hash_set::HashSet<String>,
+        output: &mut impl io::Write,
+    ) -> anyhow::Result<Delta> {
+        let sent_layer_paths = self
+            .layers
+            .iter()
+            .filter(|layer| !destination_layer_chains.contains(&layer.chain))
+            .map(|layer| layer.path.as_str())
+            .collect::<hash_set::HashSet<_>>();
+        let omitted_layer_paths = self
+            .layers
+            .iter()
+            .map(|layer| layer.path.as_str())
+            .filter(|path| !sent_layer_paths.contains(path))
+            .collect::<hash_set::HashSet<_>>();
+
+        tar::write_entries(
+            &mut self.file,
+            self.entries
+                .iter()
+                .filter(|entry| !omitted_layer_paths.contains(entry.path.as_str())),
+            output,
+        )?;
+
+        Ok(Delta {
+            layer_count: self.layers.len(),
+            sent_layer_count: self
+                .layers
+                .iter()
+                .filter(|layer| sent_layer_paths.contains(layer.path.as_str()))
+                .count(),
+        })
+    }
+}
+
+fn uses_containerd_store(driver_status: Option<Vec<Vec<String>>>) -> bool {
+    driver_status.into_iter().flatten().any(|entry| {
+        entry
+            .iter()
+            .any(|value| value.starts_with("io.containerd.snapshotter"))
+    })
+}
+
+fn get_chains(images: &[Image]) -> hash_set::HashSet<String> {
+    images
+        .iter()
+        .flat_map(|image| {
+            let layers = &image.root_fs.layers;
+            (1..=layers.len()).map(|count| chain(&layers[..count]))
+        })
+        .collect()
+}
+
+fn chain(diff_ids: &[String]) -> String {
+    diff_ids.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp;
+
+    #[test_case::test_case(None, false; "null")]
+    #[test_case::test_case(
+        Some(vec![vec!["Backing Filesystem".into(), "extfs".into()]]),
+        false;
+        "classic"
+    )]
+    #[test_case::test_case(
+        Some(vec![vec!["driver-type".into(), "io.containerd.snapshotter.v1".into()]]),
+        true;
+        "containerd"
+    )]
+    fn uses_containerd_store_handles(driver_status: Option<Vec<Vec<String>>>, expected: bool) {
+        assert_eq!(uses_containerd_store(driver_status), expected)
+    }
+
+    #[test]
+    fn gets_chains() -> anyhow::Result<()> {
+        let images = serde_json::from_str::<Vec<Image>>(
+            r#"[
+                {"RootFS": {"Type": "layers", "Layers": ["sha256:a", "sha256:b"]}},
+                {"RootFS": {"Type": "layers", "Layers": ["sha256:b"]}},
+                {"RootFS": {"Type": "layers"}}
+            ]"#,
+        )?;
+
+        assert_eq!(
+            get_chains(&images),
+            hash_set::HashSet::from([
+                "sha256:a".into(),
+                "sha256:a sha256:b".into(),
+                "sha256:b".into(),
+            ]),
+        );
+        Ok(())
+    }
+
+    #[test_case::test_case(&[], &["a/layer.tar", "b/layer.tar", "c/layer.tar"], 4; "none present")]
+    #[test_case::test_case(&["sha256:a"], &["b/layer.tar", "c/layer.tar"], 2; "base present")]
+    #[test_case::test_case(
+        &["sha256:a", "sha256:a sha256:b", "sha256:c"],
+        &["c/layer.tar"],
+        1;
+        "same layer in other chain"
+    )]
+    #[test_case::test_case(
+        &["sha256:a", "sha256:a sha256:b", "sha256:a sha256:c"],
+        &[],
+        0;
+        "all present"
+    )]
+    fn writes_delta(
+        destination_layer_chains: &[&str],
+        expected_layer_paths: &[&str],
+        expected_sent_layer_count: usize,
+    ) -> anyhow::Result<()> {
+        let folder = temp::Dir::new()?;
+        let archive_path = folder.path().join("image.tar");
+        fs::write(&archive_path, image_archive()?)?;
+
+        let mut archive = Archive::open(&archive_path)?;
+        let mut output = vec![];
+        let Delta {
+            layer_count,
+            sent_layer_count,
+        } = archive.write_delta(
+            &destination_layer_chains
+                .iter()
+                .map(|chain| chain.to_string())
+                .collect(),
+            &mut output,
+        )?;
+
+        let entries = tar::read_entries(&mut io::Cursor::new(&output))?;
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect::<Vec<_>>(),
+            [&["manifest.json", "a.json", "b.json"], expected_layer_paths,].concat(),
+        );
+        assert_eq!(layer_count, 4);
+        assert_eq!(sent_layer_count, expected_sent_layer_count);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mismatching_config() -> anyhow::Result<()> {
+        let folder = temp::Dir::new()?;
+        let archive_path = folder.path().join("image.tar");
+        let mut archive = vec![];
+        write_files(
+            &mut archive,
+            &[
+                (
+                    "manifest.json",
+                    r#"[{"Config": "a.json", "Layers": ["a/layer.tar"]}]"#,
+                ),
+                ("a.json", r#"{"rootfs": {"diff_ids": []}}"#),
+                ("a/layer.tar", "a"),
+            ],
+        )?;
+        fs::write(&archive_path, archive)?;
+
+        assert!(Archive::open(&archive_path).is_err());
+        Ok(())
+    }
+
+    // Two images as by `docker save`, sharing base layer `a` on top of which
+    // they have layers `b` and `c`, respectively.
+    fn image_archive() -> anyhow::Result<Vec<u8>> {
+        let mut archive = vec![];
+        write_files(
+            &mut archive,
+            &[
+                (
+                    "manifest.json",
+                    r#"[
+                        {"Config": "a.json", "Layers": ["a/layer.tar", "b/layer.tar"]},
+                        {"Config": "b.json", "Layers": ["a/layer.tar", "c/layer.tar"]}
+                    ]"#,
+                ),
+                (
+                    "a.json",
+                    r#"{"rootfs": {"diff_ids": ["sha256:a", "sha256:b"]}}"#,
+                ),
+                (
+                    "b.json",
+                    r#"{"rootfs": {"diff_ids": ["sha256:a", "sha256:c"]}}"#,
+                ),
+                ("a/layer.tar", "a"),
+                ("b/layer.tar", "b"),
+                ("c/layer.tar", "c"),
+            ],
+        )?;
+        Ok(archive)
+    }
+
+    fn write_files(archive: &mut Vec<u8>, files: &[(&str, &str)]) -> anyhow::Result<()> {
+        for (path, contents) in files {
+            tar::write_file(
+                archive,
+                path,
+                contents.len().try_into()?,
+                &mut contents.as_bytes(),
+            )?;
+        }
+        tar::write_end(archive)?;
+        Ok(())
+    }
+}
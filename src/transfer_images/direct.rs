@@ -0,0 +1,110 @@
+use crate::command;
+use crate::ssh;
+
+// Route from source to destination host where the source host itself connects
+// to the destination over SSH, so image data does not pass through localhost.
+pub struct Route<'a> {
+    pub container_engine: &'a str,
+    pub destination_host: &'a str,
+    pub source_host: &'a str,
+    pub ssh_cli: &'a ssh::Cli<'a>,
+}
+
+impl Route<'_> {
+    pub fn check(&self) -> anyhow::Result<()> {
+        command::status_ok(
+            self.ssh_cli
+                .command()
+                .args([self.source_host, "--"])
+                .arg(self.destination_command("true")),
+        )
+    }
+
+    pub fn transfer(&self, compress: &[String], image: &str) -> anyhow::Result<()> {
+        command::status_ok(
+            self.ssh_cli
+                .command()
+                .args([self.source_host, "--"])
+                .arg(self.transfer_script(compress, image)),
+        )
+    }
+
+    // Runs under Bash with `pipefail` so that a failing `save` fails the
+    // transfer instead of passing as long as `load` succeeds.
+    fn transfer_script(&self, compress: &[String], image: &str) -> String {
+        let save = [self.container_engine, "save", "--", image]
+            .map(ssh::quote)
+            .join(" ");
+        let compress = if compress.is_empty() {
+            String::new()
+        } else {
+            let compress = compress
+                .iter()
+                .map(|argument| ssh::quote(argument))
+                .collect::<Vec<_>>();
+            format!("{} | ", compress.join(" "))
+        };
+        let load = self.destination_command(&format!("{} load", ssh::quote(self.container_engine)));
+
+        let pipeline = format!("{save} | {compress}{load}");
+        format!("bash -o pipefail -c {}", ssh::quote(&pipeline))
+    }
+
+    fn destination_command(&self, command: &str) -> String {
+        format!(
+            "ssh -o BatchMode=yes {} {}",
+            ssh::quote(self.destination_host),
+            ssh::quote(command),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp;
+    use std::env;
+    use std::ffi;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+
+    // Runs the script locally with a fake `ssh` that consumes what is loaded.
+    #[test_case::test_case("true", true; "saved")]
+    #[test_case::test_case("false", false; "failed to save")]
+    fn transfer_script_handles(container_engine: &str, expected: bool) -> anyhow::Result<()> {
+        let folder = temp::Dir::new()?;
+        let fake_ssh = folder.path().join("ssh");
+        fs::write(&fake_ssh, "#!/bin/sh\ncat > /dev/null\n")?;
+        fs::set_permissions(&fake_ssh, fs::Permissions::from_mode(0o700))?;
+        let mut path = ffi::OsString::from(folder.path());
+        path.push(":");
+        path.push(env::var_os("PATH").unwrap_or_default());
+        let ssh_cli = ssh::Cli::new(ssh::Arguments {
+            config: None,
+            control_folder: None,
+            debug: false,
+            identity: None,
+            jump: None,
+            known_hosts: &[],
+            log_level: None,
+            options: &[],
+            port: None,
+        });
+        let route = Route {
+            container_engine,
+            destination_host: "destination",
+            source_host: "source",
+            ssh_cli: &ssh_cli,
+        };
+
+        let status = process::Command::new("sh")
+            .env("PATH", path)
+            .arg("-c")
+            .arg(route.transfer_script(&["cat".into()], "my-image"))
+            .status()?;
+
+        assert_eq!(status.success(), expected);
+        Ok(())
+    }
+}
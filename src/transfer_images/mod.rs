@@ -0,0 +1,557 @@
+pub mod compose;
+mod compression;
+mod delta;
+mod direct;
+mod resumable;
+
+use super::command;
+use super::deploy;
+use super::docker;
+use super::docker_compose;
+use super::log;
+use super::progress;
+use super::ssh;
+use super::temp;
+use anyhow::Context;
+use std::collections::hash_map;
+use std::collections::hash_set;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::sync;
+
+pub fn go(
+    In {
+        compress,
+        delta,
+        direct,
+        docker_cli,
+        docker_compose_cli,
+        dry_run,
+        force,
+        hosts,
+        images,
+        resumable,
+        source_host,
+        ssh_cli,
+    }: In,
+) -> anyhow::Result<()> {
+    let compression = compression::Compression::parse(compress)?;
+    anyhow::ensure!(
+        !(direct && matches!(compression, compression::Compression::Builtin(_))),
+        "Built-in compression is unavailable with `--direct` \
+        as the source host compresses images"
+    );
+
+    let source_docker_cli = match &source_host {
+        None => docker_cli.default_daemon(),
+        Some(source_host) => docker_cli.with_host(source_host),
+    };
+    if let Some(docker_compose_cli) = &docker_compose_cli {
+        deploy::probe(&source_docker_cli, docker_compose_cli)?;
+    }
+    let source_ssh_host = source_host
+        .as_deref()
+        .filter(|source_host| source_host.starts_with("ssh://"));
+    let destination_docker_clis = if hosts.is_empty() {
+        vec![("destination", docker_cli, None)]
+    } else {
+        hosts
+            .iter()
+            .map(|host| {
+                (
+                    host.as_str(),
+                    docker_cli.with_host(host),
+                    Some(host.as_str()),
+                )
+            })
+            .collect()
+    };
+
+    let mut destinations = destination_docker_clis
+        .into_iter()
+        .map(|(name, docker_cli, host)| {
+            let images = if force {
+                Default::default()
+            } else {
+                get_available_images(&docker_cli)
+                    .with_context(|| format!("Unable to check available images on {name:?}"))?
+            };
+            let layer_chains =
+                if delta {
+                    if delta::is_supported(&docker_cli)
+                        .with_context(|| format!("Unable to check delta support on {name:?}"))?
+                    {
+                        Some(delta::get_layer_chains(&docker_cli).with_context(|| {
+                            format!("Unable to check available layers on {name:?}")
+                        })?)
+                    } else {
+                        log::warning!(
+                            "Container engine of {name:?} cannot load partial images, \
+                        so sending all layers."
+                        );
+                        None
+                    }
+                } else {
+                    None
+                };
+            let direct_route = if direct {
+                let ssh_cli = ssh_cli.as_ref().context("Direct transfer needs SSH")?;
+                get_direct_route(&docker_cli, ssh_cli, source_ssh_host, host, name, dry_run)
+            } else {
+                None
+            };
+            let staging = if resumable {
+                let ssh_host = host
+                    .filter(|host| host.starts_with("ssh://"))
+                    .with_context(|| {
+                        format!("Resumable transfer needs SSH host as destination, not {name:?}")
+                    })?;
+                Some(resumable::Staging {
+                    container_engine: docker_cli.container_engine(),
+                    ssh_cli: ssh_cli.as_ref().context("Resumable transfer needs SSH")?,
+                    ssh_host,
+                })
+            } else {
+                None
+            };
+            Ok(Destination {
+                direct_route,
+                docker_cli,
+                failed_images: vec![],
+                images,
+                layer_chains,
+                name,
+                staging,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let images = match docker_compose_cli {
+        None => get_requested_images(images)?,
+        Some(docker_compose_cli) => compose::get_images(&docker_compose_cli, &images)
+            .context("Unable to get images of Compose project")?,
+    };
+
+    for image in images {
+        let source_image_id = if force {
+            None
+        } else {
+            get_image_id(&source_docker_cli, &image)
+        };
+        let missing_destinations = destinations
+            .iter_mut()
+            .filter(|destination| {
+                let name = destination.name;
+                match (destination.images.get(&image), &source_image_id) {
+                    (None, _) => true,
+                    (Some(image_id), Some(source_image_id)) if image_id != source_image_id => {
+                        log::info!("Replacing image {image:?} on {name:?} as its ID differs.");
+                        true
+                    }
+                    (Some(_), _) => {
+                        log::info!("Skipping image {image:?} as found on {name:?}.");
+                        false
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if missing_destinations.is_empty() {
+            continue;
+        }
+
+        let names = missing_destinations
+            .iter()
+            .map(|destination| destination.name)
+            .collect::<Vec<_>>();
+
+        if dry_run {
+            log::info!("Would transfer image {image:?} to {names:?}.");
+            continue;
+        }
+
+        log::info!("Transferring image {image:?} to {names:?}.");
+
+        let (direct_destinations, relayed_destinations) = missing_destinations
+            .into_iter()
+            .partition::<Vec<_>, _>(|destination| destination.direct_route.is_some());
+
+        let mut results = direct_destinations
+            .iter()
+            .filter_map(|destination| destination.direct_route.as_ref())
+            .map(|direct_route| direct_route.transfer(compression.arguments(), &image))
+            .collect::<Vec<_>>();
+
+        if !relayed_destinations.is_empty() {
+            results.extend(if delta || resumable {
+                transfer_buffered(
+                    &image,
+                    &source_docker_cli,
+                    &compression,
+                    delta,
+                    &relayed_destinations,
+                )
+                .with_context(|| format!("Unable to save image {image:?}"))?
+            } else {
+                transfer_full(
+                    &image,
+                    &source_docker_cli,
+                    &compression,
+                    &relayed_destinations,
+                )
+                .with_context(|| format!("Unable to save image {image:?}"))?
+            });
+        }
+
+        for (destination, result) in direct_destinations
+            .into_iter()
+            .chain(relayed_destinations)
+            .zip(results)
+        {
+            let name = destination.name;
+            match result {
+                Ok(()) => log::info!("Transferred image {image:?} to {name:?}."),
+                Err(error) => {
+                    log::error!("Unable to transfer image {image:?} to {name:?}: {error:#}");
+                    destination.failed_images.push(image.clone());
+                }
+            }
+        }
+    }
+
+    let failed_destinations = destinations
+        .iter()
+        .filter(|destination| !destination.failed_images.is_empty())
+        .map(
+            |Destination {
+                 failed_images,
+                 name,
+                 ..
+             }| format!("{name:?} ({failed_images:?})"),
+        )
+        .collect::<Vec<_>>();
+
+    if failed_destinations.is_empty() {
+        Ok(())
+    } else {
+        let failed_destinations = failed_destinations.join(", ");
+        Err(anyhow::anyhow!(
+            "Unable to transfer images to {failed_destinations}"
+        ))
+    }
+}
+
+pub struct In<'a> {
+    pub compress: Vec<String>,
+    pub delta: bool,
+    pub direct: bool,
+    pub docker_cli: docker::Cli<'a>,
+    pub docker_compose_cli: Option<docker_compose::Cli<'a>>,
+    pub dry_run: bool,
+    pub force: bool,
+    pub hosts: Vec<String>,
+    pub images: Vec<String>,
+    pub resumable: bool,
+    pub source_host: Option<String>,
+    // Only needed for direct and resumable transfers.
+    pub ssh_cli: Option<ssh::Cli<'a>>,
+}
+
+struct Destination<'a> {
+    direct_route: Option<direct::Route<'a>>,
+    docker_cli: docker::Cli<'a>,
+    failed_images: Vec<String>,
+    images: hash_map::HashMap<String, String>,
+    layer_chains: Option<hash_set::HashSet<String>>,
+    name: &'a str,
+    staging: Option<resumable::Staging<'a>>,
+}
+
+fn get_direct_route<'a>(
+    docker_cli: &docker::Cli<'a>,
+    ssh_cli: &'a ssh::Cli<'a>,
+    source_ssh_host: Option<&'a str>,
+    host: Option<&'a str>,
+    name: &str,
+    dry_run: bool,
+) -> Option<direct::Route<'a>> {
+    let (Some(source_host), Some(destination_host)) = (
+        source_ssh_host,
+        host.filter(|host| host.starts_with("ssh://")),
+    ) else {
+        log::warning!(
+            "Relaying images to {name:?} via localhost \
+            as direct transfer needs SSH hosts as source and destination."
+        );
+        return None;
+    };
+
+    let route = direct::Route {
+        container_engine: docker_cli.container_engine(),
+        destination_host,
+        source_host,
+        ssh_cli,
+    };
+    // Checking connects the hosts, which a dry run must not do.
+    if dry_run {
+        return Some(route);
+    }
+    match route.check() {
+        Ok(()) => Some(route),
+        Err(error) => {
+            log::warning!(
+                "Relaying images to {name:?} via localhost \
+                as source host is unable to connect to it: {error:#}"
+            );
+            None
+        }
+    }
+}
+
+fn transfer_full(
+    image: &str,
+    source_docker_cli: &docker::Cli,
+    compression: &compression::Compression,
+    destinations: &[&mut Destination],
+) -> anyhow::Result<Vec<anyhow::Result<()>>> {
+    let mut save = source_docker_cli.command();
+    save.args(["save", "--", image]);
+    let mut compress = compression.command();
+
+    let mut loads = destinations
+        .iter()
+        .map(|destination| {
+            let mut load = destination.docker_cli.command();
+            load.arg("load");
+            load
+        })
+        .collect::<Vec<_>>();
+
+    // Progress is measured right after saving, except when fanning out
+    // externally compressed output, whose size is unknown up front.
+    let is_compressed_fan_out = compress.is_some() && loads.len() > 1;
+    let size = if is_compressed_fan_out {
+        None
+    } else {
+        get_image_size(source_docker_cli, image)
+    };
+    let mut progress = progress::Progress::new(format!("Image {image:?}"), size);
+
+    let results = if let compression::Compression::Builtin(builtin) = compression {
+        let stats = sync::Arc::new(compression::Stats::default());
+        let results = command::piped_fan_out_ok(
+            [&mut save],
+            |stdout| builtin.encode(progress.reader(stdout), stats.clone()),
+            loads.iter_mut().collect(),
+        );
+        stats.log(image);
+        results
+    } else if let [load] = &mut loads[..] {
+        Ok(vec![command::piped_counted_ok(
+            [Some(&mut save), compress.as_mut(), Some(load)]
+                .into_iter()
+                .flatten(),
+            |count| progress.add(count),
+        )])
+    } else {
+        command::piped_fan_out_ok(
+            [Some(&mut save), compress.as_mut()].into_iter().flatten(),
+            |stdout| Ok(progress.reader(stdout)),
+            loads.iter_mut().collect(),
+        )
+    };
+    progress.finish();
+    results
+}
+
+fn get_image_size(docker_cli: &docker::Cli, image: &str) -> Option<u64> {
+    command::stdout_utf8(docker_cli.command().args([
+        "image",
+        "inspect",
+        "--format",
+        "{{.Size}}",
+        "--",
+        image,
+    ]))
+    .and_then(|size| Ok(size.trim().parse()?))
+    .inspect_err(|error| log::debug!("Unable to get size of image {image:?}: {error:#}"))
+    .ok()
+}
+
+// Saves image to a temporary file first, which allows sending only layers
+// missing on a destination and staging the archive resumably. Unlike streaming,
+// this does not show progress.
+fn transfer_buffered(
+    image: &str,
+    source_docker_cli: &docker::Cli,
+    compression: &compression::Compression,
+    delta: bool,
+    destinations: &[&mut Destination],
+) -> anyhow::Result<Vec<anyhow::Result<()>>> {
+    let folder = temp::Dir::new()?;
+    let archive_path = folder.path().join("image.tar");
+    command::status_ok(
+        source_docker_cli
+            .command()
+            .args(["save", "--output"])
+            .arg(&archive_path)
+            .args(["--", image]),
+    )?;
+    let mut archive = if delta {
+        Some(delta::Archive::open(&archive_path)?)
+    } else {
+        None
+    };
+    let full_size = fs::metadata(&archive_path)?.len();
+
+    Ok(destinations
+        .iter()
+        .enumerate()
+        .map(|(index, destination)| {
+            let name = destination.name;
+            let archive_path = match (&mut archive, &destination.layer_chains) {
+                (None, _) | (_, None) => archive_path.clone(),
+                (Some(archive), Some(layer_chains)) => {
+                    let delta_path = folder.path().join(format!("delta-{index}.tar"));
+                    let mut output = io::BufWriter::new(fs::File::create(&delta_path)?);
+                    let delta::Delta {
+                        layer_count,
+                        sent_layer_count,
+                    } = archive.write_delta(layer_chains, &mut output)?;
+                    output.flush()?;
+                    let sent_size = fs::metadata(&delta_path)?.len();
+
+                    log::info!(
+                        "Sending {sent_layer_count} of {layer_count} layers to {name:?}, \
+                        {sent_size} of {full_size} bytes."
+                    );
+                    delta_path
+                }
+            };
+
+            let archive_path = match compression {
+                compression::Compression::Builtin(builtin) => {
+                    let compressed_path = folder.path().join(format!("compressed-{index}"));
+                    let stats = sync::Arc::new(compression::Stats::default());
+                    io::copy(
+                        &mut builtin.encode(fs::File::open(&archive_path)?, stats.clone())?,
+                        &mut fs::File::create(&compressed_path)?,
+                    )?;
+                    stats.log(image);
+                    compressed_path
+                }
+                compression::Compression::Command(_) | compression::Compression::None => {
+                    archive_path
+                }
+            };
+
+            let mut compress = compression.command();
+
+            match &destination.staging {
+                None => {
+                    let mut load = destination.docker_cli.command();
+                    load.arg("load");
+                    compress
+                        .as_mut()
+                        .unwrap_or(&mut load)
+                        .stdin(fs::File::open(&archive_path)?);
+
+                    command::piped_ok([compress.as_mut(), Some(&mut load)].into_iter().flatten())
+                }
+                Some(staging) => {
+                    let archive_path = match &mut compress {
+                        None => archive_path,
+                        Some(compress) => {
+                            let compressed_path = folder.path().join(format!("compressed-{index}"));
+                            command::status_ok(
+                                compress
+                                    .stdin(fs::File::open(&archive_path)?)
+                                    .stdout(fs::File::create(&compressed_path)?),
+                            )?;
+                            compressed_path
+                        }
+                    };
+                    staging.send(&archive_path)
+                }
+            }
+        })
+        .collect())
+}
+
+// Maps the names of available images to their IDs, where an image is named in
+// any of the forms `<namespace>:<tag>`, `<namespace>@<digest>`, or
+// `<namespace>:<tag>@<digest>`.
+pub fn get_available_images(
+    docker_cli: &docker::Cli,
+) -> anyhow::Result<hash_map::HashMap<String, String>> {
+    Ok(parse_available_images(&command::stdout_utf8(
+        docker_cli.command().args([
+            "images",
+            "--format",
+            "{{.ID}} {{.Repository}}:{{.Tag}}
+{{.ID}} {{.Repository}}@{{.Digest}}
+{{.ID}} {{.Repository}}:{{.Tag}}@{{.Digest}}",
+            "--no-trunc",
+        ]),
+    )?))
+}
+
+fn parse_available_images(output: &str) -> hash_map::HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (image_id, image) = line.split_once(' ')?;
+            Some((image.into(), docker::normalize_image_id(image_id)))
+        })
+        .collect()
+}
+
+fn get_image_id(docker_cli: &docker::Cli, image: &str) -> Option<String> {
+    command::stdout_utf8(
+        docker_cli
+            .command()
+            .args(["image", "inspect", "--format", "{{.Id}}", "--", image]),
+    )
+    .map(|image_id| docker::normalize_image_id(image_id.trim()))
+    .inspect_err(|error| log::debug!("Unable to get ID of image {image:?}: {error:#}"))
+    .ok()
+}
+
+fn get_requested_images(mut argument_images: Vec<String>) -> anyhow::Result<Vec<String>> {
+    Ok(
+        match argument_images.iter().position(|image| image == "-") {
+            None => argument_images,
+            Some(stdin_index) => {
+                let stdin_images = io::stdin()
+                    .lines()
+                    .collect::<io::Result<Vec<_>>>()
+                    .context("Unable to read stdin lines")?;
+                argument_images.splice(stdin_index..=stdin_index, stdin_images);
+                argument_images
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_available_images() {
+        assert_eq!(
+            parse_available_images(
+                "sha256:123 alpine:3.22
+sha256:123 alpine@sha256:abc
+456 localhost/kerek:latest
+"
+            ),
+            hash_map::HashMap::from([
+                ("alpine:3.22".into(), "123".into()),
+                ("alpine@sha256:abc".into(), "123".into()),
+                ("localhost/kerek:latest".into(), "456".into()),
+            ]),
+        )
+    }
+}
@@ -0,0 +1,280 @@
+use crate::command;
+use crate::log;
+use crate::ssh;
+use anyhow::Context;
+use sha2::Digest;
+use std::collections::hash_set;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+use std::process;
+use std::sync;
+use std::thread;
+use std::time;
+
+const CHUNK_SIZE: usize = 1 << 24;
+const MAX_CHUNK_ATTEMPTS: u32 = 5;
+const STAGING_FOLDER: &str = ".cache/kerek/transfers";
+const STALE_STAGING_DAYS: u32 = 7;
+
+// Stages an image archive in a folder on the destination host, chunk by chunk,
+// each verified by its SHA-256 checksum. The folder is named by the checksum of
+// the whole archive, so a later attempt to send the same archive resumes with
+// the chunks that are staged already. The folder is removed after loading.
+//
+// Resuming needs `docker save` and any compression to output the same archive
+// again. Otherwise, a failed attempt leaves its folder behind, which is why
+// staging folders untouched for some days are removed.
+pub struct Staging<'a> {
+    pub container_engine: &'a str,
+    pub ssh_cli: &'a ssh::Cli<'a>,
+    pub ssh_host: &'a str,
+}
+
+// Runs shell scripts on the destination host, in its home folder.
+struct Remote<'a, F: Fn() -> process::Command> {
+    container_engine: &'a str,
+    shell: F,
+}
+
+impl Staging<'_> {
+    pub fn send(&self, archive_path: &path::Path) -> anyhow::Result<()> {
+        Remote {
+            container_engine: self.container_engine,
+            shell: || {
+                let mut command = self.ssh_cli.command();
+                command.args([self.ssh_host, "--"]);
+                command
+            },
+        }
+        .send(archive_path, CHUNK_SIZE)
+    }
+}
+
+impl<F: Fn() -> process::Command> Remote<'_, F> {
+    fn send(&self, archive_path: &path::Path, chunk_size: usize) -> anyhow::Result<()> {
+        let (archive_checksum, chunk_checksums) = get_checksums(
+            &mut fs::File::open(archive_path)
+                .with_context(|| format!("Unable to open {archive_path:?}"))?,
+            chunk_size,
+        )?;
+        let folder = format!("{STAGING_FOLDER}/{archive_checksum}");
+
+        let staged_chunks = self.get_staged_chunks(&folder)?;
+        let mut archive = fs::File::open(archive_path)?;
+
+        for (index, checksum) in chunk_checksums.iter().enumerate() {
+            let chunk_name = chunk_name(index);
+            let mut chunk = vec![];
+            (&mut archive)
+                .take(chunk_size as u64)
+                .read_to_end(&mut chunk)?;
+
+            if staged_chunks.contains(&(checksum.clone(), chunk_name.clone())) {
+                log::debug!("Resuming after staged chunk {chunk_name:?}.");
+                continue;
+            }
+
+            let chunk_count = chunk_checksums.len();
+            log::debug!("Staging chunk {} of {chunk_count}.", index + 1);
+            self.stage_chunk(&folder, &chunk_name, checksum, chunk)
+                .with_context(|| format!("Unable to stage chunk {chunk_name:?}"))?;
+        }
+
+        command::status_ok(&mut self.script(format!(
+            "cd {folder} && cat -- *.chunk | {container_engine} load \
+            && cd && rm -r -- {folder}",
+            container_engine = ssh::quote(self.container_engine),
+            folder = ssh::quote(&folder),
+        )))
+    }
+
+    // Also removes stale staging folders, after touching the given one.
+    fn get_staged_chunks(
+        &self,
+        folder: &str,
+    ) -> anyhow::Result<hash_set::HashSet<(String, String)>> {
+        let mut command = self.script(format!(
+            "mkdir -p -- {folder} && touch -- {folder} \
+            && find {staging_folder} -mindepth 1 -maxdepth 1 -type d \
+            -mtime +{STALE_STAGING_DAYS} -exec rm -r -- {{}} + \
+            && cd {folder} && {{ sha256sum -- *.chunk 2>/dev/null || true; }}",
+            folder = ssh::quote(folder),
+            staging_folder = ssh::quote(STAGING_FOLDER),
+        ));
+
+        Ok(command::stdout_utf8(&mut command)?
+            .lines()
+            .filter_map(|line| {
+                let (checksum, name) = line.split_once("  ")?;
+                Some((checksum.into(), name.into()))
+            })
+            .collect())
+    }
+
+    fn stage_chunk(
+        &self,
+        folder: &str,
+        chunk_name: &str,
+        checksum: &str,
+        chunk: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let script = format!(
+            "cd {folder} && cat > {part} \
+            && printf '%s  %s\\n' {checksum} {part} | sha256sum --check --status \
+            && mv -- {part} {chunk_name}",
+            checksum = ssh::quote(checksum),
+            chunk_name = ssh::quote(chunk_name),
+            folder = ssh::quote(folder),
+            part = ssh::quote(&format!("{chunk_name}.part")),
+        );
+        let chunk = sync::Arc::<[u8]>::from(chunk);
+
+        let mut attempt = 1;
+        loop {
+            match command::stdin_ok(chunk.clone(), &mut self.script(script.clone())) {
+                Ok(()) => break Ok(()),
+                Err(error) if attempt < MAX_CHUNK_ATTEMPTS => {
+                    log::warning!("Attempt {attempt} failed, retrying: {error:#}");
+                    thread::sleep(time::Duration::from_secs(2 << attempt));
+                    attempt += 1;
+                }
+                Err(error) => break Err(error),
+            }
+        }
+    }
+
+    fn script(&self, script: String) -> process::Command {
+        let mut command = (self.shell)();
+        command.arg(script);
+        command
+    }
+}
+
+fn chunk_name(index: usize) -> String {
+    format!("{index:06}.chunk")
+}
+
+fn get_checksums(archive: &mut impl Read, chunk_size: usize) -> io::Result<(String, Vec<String>)> {
+    let mut archive_hasher = sha2::Sha256::new();
+    let mut chunk_checksums = vec![];
+    let mut chunk = vec![];
+
+    loop {
+        chunk.clear();
+        archive.take(chunk_size as u64).read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+        archive_hasher.update(&chunk);
+        chunk_checksums.push(format!("{:x}", sha2::Sha256::digest(&chunk)));
+    }
+
+    Ok((format!("{:x}", archive_hasher.finalize()), chunk_checksums))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp;
+    use std::env;
+    use std::ffi;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::atomic;
+
+    #[test]
+    fn gets_checksums() -> io::Result<()> {
+        assert_eq!(
+            get_checksums(&mut &b"abc"[..], 2)?,
+            (
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into(),
+                vec![
+                    "fb8e20fc2e4c3f248c60c39bd652f3c1347298bb977b8b4d5903b85055620603".into(),
+                    "2e7d2c03a9507ae265ecf5b5356885a53393a2029d241394997265a1a25aefc6".into(),
+                ],
+            ),
+        );
+        assert_eq!(get_checksums(&mut io::empty(), 2)?.1, Vec::<String>::new());
+        Ok(())
+    }
+
+    #[test_case::test_case(&[], 5; "fresh")]
+    #[test_case::test_case(&[(0, "ab")], 4; "resumed")]
+    #[test_case::test_case(&[(0, "ab"), (1, "xx")], 4; "corrupt chunk")]
+    #[test_case::test_case(&[(0, "ab"), (1, "cd"), (2, "e")], 2; "all staged")]
+    fn sends(staged_chunks: &[(usize, &str)], expected_command_count: usize) -> anyhow::Result<()> {
+        let home = temp::Dir::new()?;
+        let archive_path = home.path().join("image.tar");
+        fs::write(&archive_path, "abcde")?;
+        let (archive_checksum, _) = get_checksums(&mut &b"abcde"[..], 2)?;
+        let folder = home.path().join(STAGING_FOLDER).join(archive_checksum);
+        fs::create_dir_all(&folder)?;
+        for (index, contents) in staged_chunks {
+            fs::write(folder.join(chunk_name(*index)), contents)?;
+        }
+
+        let command_count = atomic::AtomicUsize::new(0);
+        remote(&home, &command_count)?.send(&archive_path, 2)?;
+
+        assert_eq!(fs::read_to_string(home.path().join("loaded.tar"))?, "abcde");
+        assert!(!folder.exists());
+        assert_eq!(command_count.into_inner(), expected_command_count);
+        Ok(())
+    }
+
+    #[test]
+    fn removes_stale_staging_folders() -> anyhow::Result<()> {
+        let home = temp::Dir::new()?;
+        let archive_path = home.path().join("image.tar");
+        fs::write(&archive_path, "abcde")?;
+        let staging_folder = home.path().join(STAGING_FOLDER);
+        for (name, age_days) in [("fresh", 1), ("stale", 30)] {
+            fs::create_dir_all(staging_folder.join(name))?;
+            fs::File::open(staging_folder.join(name))?.set_modified(
+                time::SystemTime::now() - time::Duration::from_secs(age_days * 24 * 60 * 60),
+            )?;
+        }
+
+        remote(&home, &atomic::AtomicUsize::new(0))?.send(&archive_path, 2)?;
+
+        let mut names = fs::read_dir(&staging_folder)?
+            .map(|entry| Ok(entry?.file_name().into_string().unwrap_or_default()))
+            .collect::<io::Result<Vec<_>>>()?;
+        names.sort();
+        assert_eq!(names, ["fresh"]);
+        Ok(())
+    }
+
+    // Runs scripts locally with given folder as home and a fake container
+    // engine that keeps the loaded archive.
+    fn remote<'a>(
+        home: &'a temp::Dir,
+        command_count: &'a atomic::AtomicUsize,
+    ) -> anyhow::Result<Remote<'a, impl Fn() -> process::Command>> {
+        let container_engine = home.path().join("engine");
+        fs::write(
+            &container_engine,
+            "#!/bin/sh\ntest \"$1\" = load && cat > \"${HOME}/loaded.tar\"\n",
+        )?;
+        fs::set_permissions(&container_engine, fs::Permissions::from_mode(0o700))?;
+
+        let mut path = ffi::OsString::from(home.path());
+        path.push(":");
+        path.push(env::var_os("PATH").unwrap_or_default());
+
+        Ok(Remote {
+            container_engine: "engine",
+            shell: move || {
+                command_count.fetch_add(1, atomic::Ordering::SeqCst);
+                let mut command = process::Command::new("sh");
+                command
+                    .current_dir(home.path())
+                    .env("HOME", home.path())
+                    .env("PATH", &path)
+                    .arg("-c");
+                command
+            },
+        })
+    }
+}
@@ -0,0 +1,224 @@
+mod scoped;
+mod supervisor;
+
+use super::command;
+use super::docker;
+use super::log;
+use super::ssh;
+use super::temp;
+use anyhow::Context;
+use std::net;
+use std::path;
+use std::process;
+
+// Returns exit code to exit with, which is nonzero only if a command is run
+// and fails.
+pub fn go(
+    In {
+        command,
+        container_engine,
+        dry_run,
+        foreground,
+        local_address,
+        local_socket,
+        remote_socket,
+        ssh_cli,
+        ssh_host,
+        stop,
+    }: In,
+) -> anyhow::Result<i32> {
+    let local = match local_address {
+        None => Local::Socket(
+            path::absolute(&local_socket)
+                .with_context(|| format!("Unable to make {local_socket:?} absolute"))?,
+        ),
+        Some(local_address) => Local::Address(local_address),
+    };
+
+    if stop {
+        supervisor::stop(&local, dry_run)?;
+        return Ok(0);
+    }
+
+    let ssh_host = ssh_host.context("SSH host is missing")?;
+    let remote_socket = match remote_socket {
+        None => infer_remote_socket(RemoteConfig {
+            container_engine: &container_engine,
+            ssh_cli: &ssh_cli,
+            ssh_host: &ssh_host,
+        })
+        .context("Unable to infer remote socket")?,
+        Some(remote_socket) => remote_socket,
+    };
+
+    let pid_path = supervisor::pid_path(&local)?;
+
+    // A scoped tunnel uses a private socket, removed with its folder.
+    let folder = if command.is_empty() || matches!(local, Local::Address(_)) {
+        None
+    } else {
+        Some(temp::Dir::new()?)
+    };
+    let local = match (&folder, local) {
+        (None, Local::Address(address)) => Local::Address(bind_address(address)?),
+        (None, local) => local,
+        (Some(folder), _) => Local::Socket(folder.path().join("kerek.sock")),
+    };
+    let is_child = foreground || !command.is_empty();
+
+    let mut ssh = ssh_cli.dedicated_command();
+    ssh.args((!is_child).then_some("-f").iter())
+        .args([
+            "-N",
+            "-o",
+            &format!("LocalForward {} {remote_socket:?}", local.forward_spec()),
+            "-o",
+            "StreamLocalBindUnlink=yes", // Required to reuse socket file.
+        ])
+        // Lets failing forwarding end a child tunnel instead of hanging.
+        .args(
+            is_child
+                .then_some(["-o", "ExitOnForwardFailure=yes"])
+                .into_iter()
+                .flatten(),
+        )
+        .arg(&ssh_host)
+        // Keeps stdout free for the Docker host, even if SSH forks.
+        .stdout(process::Stdio::null());
+
+    if let Local::Address(address) = &local {
+        log::warning!(
+            "TCP port {address} is unauthenticated: any local user or process \
+            can control the Docker host through it."
+        );
+    }
+
+    if dry_run {
+        log::info!("Would run: {ssh:?}");
+        if !command.is_empty() {
+            log::info!("Would run with tunnel: {command:?}");
+        }
+        Ok(0)
+    } else if !command.is_empty() {
+        scoped::run(&local, ssh, &command)
+    } else if foreground {
+        supervisor::run(&local, &pid_path, ssh)?;
+        Ok(0)
+    } else {
+        command::status_ok(&mut ssh)?;
+        if let Local::Address(_) = local {
+            println!("{}", local.docker_host());
+        }
+        Ok(0)
+    }
+}
+
+pub struct In<'a> {
+    pub command: Vec<String>,
+    pub container_engine: String,
+    pub dry_run: bool,
+    pub foreground: bool,
+    pub local_address: Option<net::SocketAddr>,
+    pub local_socket: String,
+    pub remote_socket: Option<String>,
+    pub ssh_cli: ssh::Cli<'a>,
+    pub ssh_host: Option<String>,
+    pub stop: bool,
+}
+
+// Local end of tunnel.
+enum Local {
+    Address(net::SocketAddr),
+    Socket(path::PathBuf),
+}
+
+impl Local {
+    fn docker_host(&self) -> String {
+        match self {
+            Self::Address(address) => format!("tcp://{address}"),
+            Self::Socket(socket) => format!("unix://{}", socket.display()),
+        }
+    }
+
+    fn forward_spec(&self) -> String {
+        match self {
+            Self::Address(address) => address.to_string(),
+            Self::Socket(socket) => format!("{socket:?}"),
+        }
+    }
+}
+
+pub struct RemoteConfig<'a> {
+    pub container_engine: &'a str,
+    pub ssh_cli: &'a ssh::Cli<'a>,
+    pub ssh_host: &'a str,
+}
+
+pub fn infer_remote_socket(
+    RemoteConfig {
+        container_engine,
+        ssh_cli,
+        ssh_host,
+    }: RemoteConfig,
+) -> anyhow::Result<String> {
+    let mut command = ssh_cli.command();
+    command.arg(ssh_host);
+
+    match docker::EngineKind::from_program(container_engine) {
+        docker::EngineKind::Docker => command.args([
+            container_engine,
+            "context",
+            "inspect",
+            "--format",
+            "{{.Endpoints.docker.Host}}",
+        ]),
+        docker::EngineKind::Podman => command.args([
+            container_engine,
+            "info",
+            "--format",
+            "{{.Host.RemoteSocket.Path}}",
+        ]),
+        docker::EngineKind::Other => {
+            return Err(anyhow::anyhow!(
+                "Unsupported container engine {container_engine:?}, \
+                pass `--remote-socket` explicitly"
+            ));
+        }
+    };
+
+    let socket = command::stdout_utf8(&mut command)?;
+    let socket = socket.trim_end();
+    let socket = socket.strip_prefix("unix://").unwrap_or(socket);
+
+    log::info!("Inferred remote socket: {socket:?}");
+    Ok(socket.into())
+}
+
+// Picks a free port if port 0 is given, by binding it briefly. Another process
+// may take the port before SSH binds it, in which case SSH fails to forward it,
+// and the tunnel needs to be set up again.
+fn bind_address(address: net::SocketAddr) -> anyhow::Result<net::SocketAddr> {
+    anyhow::ensure!(
+        address.ip().is_loopback(),
+        "Local address {address} must be a loopback address"
+    );
+    if address.port() != 0 {
+        return Ok(address);
+    }
+    let address = net::TcpListener::bind(address)
+        .and_then(|listener| listener.local_addr())
+        .with_context(|| format!("Unable to find free port for {address}"))?;
+    log::info!("Picked free port {}.", address.port());
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_non_loopback_address() -> anyhow::Result<()> {
+        assert!(bind_address("0.0.0.0:2375".parse()?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use crate::log;
+use anyhow::Context;
+use std::os::unix::process::CommandExt;
+use std::os::unix::process::ExitStatusExt;
+use std::process;
+use std::sync::atomic;
+use std::thread;
+use std::time;
+
+const STARTUP_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+static COMMAND_PID: atomic::AtomicI32 = atomic::AtomicI32::new(0);
+static PENDING_SIGNAL: atomic::AtomicI32 = atomic::AtomicI32::new(0);
+
+// Runs command with `DOCKER_HOST` pointed at SSH tunnel, which is torn down
+// afterwards in any case, even on SIGINT or SIGTERM, which are forwarded to the
+// command. Returns the exit code of the command.
+pub fn run(
+    local: &super::Local,
+    mut ssh: process::Command,
+    command: &[String],
+) -> anyhow::Result<i32> {
+    handle_signals()?;
+
+    // Keeps SSH out of the terminal's process group, which gets SIGINT on
+    // Ctrl+C, so that the tunnel outlives the command.
+    let mut tunnel = ssh
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Unable to run command: {ssh:?}"))?;
+
+    let result = (|| {
+        wait_until_ready(local, &mut tunnel)?;
+
+        let (program, arguments) = command.split_first().context("Command is missing")?;
+        let mut child = process::Command::new(program)
+            .args(arguments)
+            .env("DOCKER_HOST", local.docker_host())
+            .env_remove("DOCKER_CONTEXT")
+            .spawn()
+            .with_context(|| format!("Unable to run command {command:?}"))?;
+
+        COMMAND_PID.store(child.id().try_into()?, atomic::Ordering::SeqCst);
+        let signal = PENDING_SIGNAL.swap(0, atomic::Ordering::SeqCst);
+        if signal != 0 {
+            handle_signal(signal);
+        }
+        let status = child.wait();
+        COMMAND_PID.store(0, atomic::Ordering::SeqCst);
+
+        let status = status.with_context(|| format!("Unable to run command {command:?}"))?;
+        Ok(exit_code(status))
+    })();
+
+    if tunnel.try_wait()?.is_none() {
+        tunnel.kill()?;
+    }
+    tunnel.wait()?;
+
+    result
+}
+
+fn wait_until_ready(local: &super::Local, tunnel: &mut process::Child) -> anyhow::Result<()> {
+    let start = time::Instant::now();
+
+    loop {
+        if let Some(status) = tunnel.try_wait()? {
+            anyhow::bail!("SSH tunnel exited with {status}");
+        }
+        let signal = PENDING_SIGNAL.load(atomic::Ordering::SeqCst);
+        if signal != 0 {
+            anyhow::bail!("Interrupted by signal {signal}");
+        }
+
+        match super::supervisor::ping(local) {
+            Ok(()) => return Ok(()),
+            Err(error) if start.elapsed() >= STARTUP_TIMEOUT => {
+                return Err(error.context("SSH tunnel is not ready"));
+            }
+            Err(error) => log::debug!("Waiting for SSH tunnel: {error:#}"),
+        }
+
+        thread::sleep(time::Duration::from_millis(200));
+    }
+}
+
+fn handle_signals() -> anyhow::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: The handler only touches atomics and calls `kill`, which is
+        // async-signal-safe.
+        let previous_handler = unsafe { libc::signal(signal, handler) };
+        anyhow::ensure!(
+            previous_handler != libc::SIG_ERR,
+            "Unable to handle signal {signal}"
+        );
+        // Keeps signal ignored as in background jobs of a shell.
+        if previous_handler == libc::SIG_IGN {
+            // SAFETY: Ignoring a signal is always sound.
+            unsafe { libc::signal(signal, libc::SIG_IGN) };
+        }
+    }
+    Ok(())
+}
+
+// Forwards signal to the command if running, otherwise keeps it pending.
+extern "C" fn handle_signal(signal: libc::c_int) {
+    let pid = COMMAND_PID.load(atomic::Ordering::SeqCst);
+    if pid == 0 {
+        PENDING_SIGNAL.store(signal, atomic::Ordering::SeqCst);
+    } else {
+        // SAFETY: Sending a signal has no memory safety implications.
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+// Follows the shell convention for commands terminated by a signal.
+fn exit_code(status: process::ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case::test_case("exit 0", 0; "success")]
+    #[test_case::test_case("exit 3", 3; "failure")]
+    #[test_case::test_case("kill -TERM $$", 143; "signal")]
+    fn gets_exit_code(script: &str, expected: i32) -> anyhow::Result<()> {
+        let status = process::Command::new("bash")
+            .args(["-c", script])
+            .status()?;
+        assert_eq!(exit_code(status), expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+use crate::command;
+use crate::log;
+use anyhow::Context;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net;
+use std::os::unix;
+use std::path;
+use std::process;
+use std::thread;
+use std::time;
+
+const MAX_FAILED_PINGS: u32 = 3;
+const MAX_STARTUP_ATTEMPTS: u32 = 5;
+const MAX_RECONNECT_DELAY: time::Duration = time::Duration::from_secs(60);
+const PING_INTERVAL: time::Duration = time::Duration::from_secs(10);
+const PING_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+const STARTUP_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+// Runs SSH tunnel as child process until killed, checking the local end of the
+// tunnel with a Docker `_ping` and reconnecting whenever SSH exits or pings keep
+// failing. The PIDs of this supervisor and of the current SSH process are
+// recorded in a PID file for `stop`. If the tunnel never becomes healthy in the
+// first attempts, like with an authentication error, the supervisor gives up.
+pub fn run(
+    local: &super::Local,
+    pid_path: &path::Path,
+    mut ssh: process::Command,
+) -> anyhow::Result<()> {
+    if let Some(pid) = read_pids(pid_path)?.first()
+        && is_process(pid, ProcessRole::Supervisor)
+    {
+        anyhow::bail!("Tunnel is already supervised by process {pid}, see {pid_path:?}");
+    }
+
+    let mut reconnect_delay = time::Duration::from_secs(1);
+    let mut has_been_healthy = false;
+    let mut attempt = 1;
+
+    loop {
+        let mut child = ssh
+            .spawn()
+            .with_context(|| format!("Unable to run command: {ssh:?}"))?;
+        write_pids(pid_path, child.id())?;
+
+        let outcome = supervise(local, &mut child);
+
+        if child.try_wait()?.is_none() {
+            child.kill()?;
+        }
+        child.wait()?;
+
+        match outcome {
+            Outcome::Exited {
+                status,
+                was_healthy,
+            } => {
+                log::warning!("SSH tunnel exited with {status}.");
+                if was_healthy {
+                    has_been_healthy = true;
+                    reconnect_delay = time::Duration::from_secs(1);
+                }
+            }
+            Outcome::Unhealthy(error) => {
+                log::warning!("SSH tunnel is unhealthy: {error:#}");
+            }
+            Outcome::WasHealthy(error) => {
+                log::warning!("SSH tunnel became unhealthy: {error:#}");
+                has_been_healthy = true;
+                reconnect_delay = time::Duration::from_secs(1);
+            }
+        }
+
+        if !has_been_healthy && attempt >= MAX_STARTUP_ATTEMPTS {
+            remove_file(pid_path)?;
+            anyhow::bail!("SSH tunnel did not become healthy in {attempt} attempts, giving up");
+        }
+        attempt += 1;
+
+        log::info!("Reconnecting in {}s.", reconnect_delay.as_secs());
+        thread::sleep(reconnect_delay);
+        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+// Tears down tunnel supervised by `run`, stopping the supervisor before SSH so
+// that it does not reconnect.
+pub fn stop(local: &super::Local, dry_run: bool) -> anyhow::Result<()> {
+    let pid_path = pid_path(local)?;
+    let pids = read_pids(&pid_path)?;
+    anyhow::ensure!(
+        !pids.is_empty(),
+        "No supervised tunnel found at {pid_path:?}"
+    );
+
+    for (pid, role) in pids
+        .into_iter()
+        .zip([ProcessRole::Supervisor, ProcessRole::Ssh])
+    {
+        // The PID file may be stale, with its PIDs reused by other processes.
+        if !is_process(&pid, role) {
+            log::debug!("Process {pid} is not the expected one, assuming it ended.");
+            continue;
+        }
+
+        let mut command = process::Command::new("kill");
+        command.args(["-TERM", "--", &pid]);
+
+        if dry_run {
+            log::info!("Would run: {command:?}");
+        } else if let Err(error) = command::status_ok(&mut command) {
+            log::debug!("Unable to kill process {pid}, assuming it ended: {error:#}");
+        }
+    }
+
+    if !dry_run {
+        let local_socket = match local {
+            super::Local::Address(_) => None,
+            super::Local::Socket(socket) => Some(socket.as_path()),
+        };
+        for path in [Some(pid_path.as_path()), local_socket]
+            .into_iter()
+            .flatten()
+        {
+            remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+enum Outcome {
+    Exited {
+        status: process::ExitStatus,
+        was_healthy: bool,
+    },
+    Unhealthy(anyhow::Error),
+    WasHealthy(anyhow::Error),
+}
+
+fn supervise(local: &super::Local, child: &mut process::Child) -> Outcome {
+    let start = time::Instant::now();
+    let mut failed_pings = 0;
+    let mut is_healthy = false;
+
+    loop {
+        match child.try_wait() {
+            Ok(None) => {}
+            Ok(Some(status)) => {
+                return Outcome::Exited {
+                    status,
+                    was_healthy: is_healthy,
+                };
+            }
+            Err(error) => return Outcome::Unhealthy(error.into()),
+        }
+
+        match ping(local) {
+            Ok(()) => {
+                if !is_healthy {
+                    log::info!("SSH tunnel is healthy.");
+                    is_healthy = true;
+                }
+                failed_pings = 0;
+            }
+            Err(error) if is_healthy => {
+                failed_pings += 1;
+                log::debug!("Ping {failed_pings} of {MAX_FAILED_PINGS} failed: {error:#}");
+                if failed_pings >= MAX_FAILED_PINGS {
+                    return Outcome::WasHealthy(error);
+                }
+            }
+            Err(error) if start.elapsed() >= STARTUP_TIMEOUT => {
+                return Outcome::Unhealthy(error);
+            }
+            Err(error) => log::debug!("Waiting for SSH tunnel: {error:#}"),
+        }
+
+        thread::sleep(if is_healthy {
+            PING_INTERVAL
+        } else {
+            time::Duration::from_secs(1)
+        });
+    }
+}
+
+// Checks Docker host behind local end of tunnel with a `_ping` request.
+pub fn ping(local: &super::Local) -> anyhow::Result<()> {
+    let response = match local {
+        super::Local::Address(address) => {
+            let stream = net::TcpStream::connect_timeout(address, PING_TIMEOUT)?;
+            stream.set_read_timeout(Some(PING_TIMEOUT))?;
+            stream.set_write_timeout(Some(PING_TIMEOUT))?;
+            request_ping(stream)?
+        }
+        super::Local::Socket(socket) => {
+            let stream = unix::net::UnixStream::connect(socket)?;
+            stream.set_read_timeout(Some(PING_TIMEOUT))?;
+            stream.set_write_timeout(Some(PING_TIMEOUT))?;
+            request_ping(stream)?
+        }
+    };
+    check_ping_response(&response)
+}
+
+fn request_ping(mut stream: impl Read + Write) -> io::Result<String> {
+    stream.write_all(b"GET /_ping HTTP/1.1\r\nHost: docker\r\nConnection: close\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+fn check_ping_response(response: &str) -> anyhow::Result<()> {
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "Unexpected response to Docker ping: {status_line:?}"
+        )),
+    }
+}
+
+// Named after the local end as requested, which for a TCP port of 0 is not the
+// port picked in the end, so that `stop` finds the file with the same request.
+pub fn pid_path(local: &super::Local) -> anyhow::Result<path::PathBuf> {
+    Ok(match local {
+        super::Local::Address(address) => path::absolute(format!("kerek-{}.pid", address.port()))?,
+        super::Local::Socket(socket) => {
+            let mut path = socket.as_os_str().to_owned();
+            path.push(".pid");
+            path.into()
+        }
+    })
+}
+
+fn read_pids(pid_path: &path::Path) -> anyhow::Result<Vec<String>> {
+    match fs::read_to_string(pid_path) {
+        Ok(pids) => Ok(pids.lines().map(|pid| pid.into()).collect()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(error).with_context(|| format!("Unable to read {pid_path:?}")),
+    }
+}
+
+fn write_pids(pid_path: &path::Path, ssh_pid: u32) -> anyhow::Result<()> {
+    fs::write(pid_path, format!("{}\n{ssh_pid}\n", process::id()))
+        .with_context(|| format!("Unable to write {pid_path:?}"))
+}
+
+fn remove_file(path: &path::Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            Err(error).with_context(|| format!("Unable to remove {path:?}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ProcessRole {
+    Supervisor,
+    Ssh,
+}
+
+// Checks that a process is alive and plays given role, judging by its command.
+// Errors of `ps` for gone processes are expected, hence not shown.
+fn is_process(pid: &str, role: ProcessRole) -> bool {
+    process::Command::new("ps")
+        .args(["-o", "args=", "-p", pid])
+        .stderr(process::Stdio::null())
+        .output()
+        .is_ok_and(|output| {
+            output.status.success() && is_role(&String::from_utf8_lossy(&output.stdout), role)
+        })
+}
+
+fn is_role(arguments: &str, role: ProcessRole) -> bool {
+    let mut arguments = arguments.split_whitespace();
+    let Some(program) = arguments.next().map(path::Path::new) else {
+        return false;
+    };
+    match role {
+        ProcessRole::Supervisor => {
+            program
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().contains("kerek"))
+                && arguments.any(|argument| argument == "tunnel-ssh")
+        }
+        ProcessRole::Ssh => program.ends_with("ssh"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp;
+
+    #[test_case::test_case("HTTP/1.1 200 OK\r\nApi-Version: 1.47\r\n\r\nOK", true; "ok")]
+    #[test_case::test_case("HTTP/1.1 500 Internal Server Error\r\n\r\n", false; "error")]
+    #[test_case::test_case("", false; "empty")]
+    fn checks_ping_response(response: &str, expected: bool) {
+        assert_eq!(check_ping_response(response).is_ok(), expected)
+    }
+
+    #[test]
+    fn pings_socket() -> anyhow::Result<()> {
+        let folder = temp::Dir::new()?;
+        let socket = folder.path().join("docker.sock");
+        let listener = unix::net::UnixListener::bind(&socket)?;
+        let server = thread::spawn(move || serve_ping(listener.accept()?.0));
+
+        ping(&super::super::Local::Socket(socket))?;
+        let request = server.join().expect("Server panicked")?;
+        assert!(request.starts_with("GET /_ping HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn pings_address() -> anyhow::Result<()> {
+        let address = super::super::bind_address("127.0.0.1:0".parse()?)?;
+        assert_ne!(address.port(), 0);
+        let listener = net::TcpListener::bind(address)?;
+        let server = thread::spawn(move || serve_ping(listener.accept()?.0));
+
+        ping(&super::super::Local::Address(address))?;
+        let request = server.join().expect("Server panicked")?;
+        assert!(request.starts_with("GET /_ping HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test_case::test_case("127.0.0.1:2375", "kerek-2375.pid"; "fixed port")]
+    #[test_case::test_case("127.0.0.1:0", "kerek-0.pid"; "free port")]
+    fn gets_pid_path_of_address(address: &str, expected: &str) -> anyhow::Result<()> {
+        let pid_path = pid_path(&super::super::Local::Address(address.parse()?))?;
+        assert_eq!(pid_path.file_name(), Some(expected.as_ref()));
+        Ok(())
+    }
+
+    #[test]
+    fn handles_pid_file() -> anyhow::Result<()> {
+        let folder = temp::Dir::new()?;
+        let pid_path = pid_path(&super::super::Local::Socket(
+            folder.path().join("kerek.sock"),
+        ))?;
+        assert_eq!(pid_path.file_name(), Some("kerek.sock.pid".as_ref()));
+        assert_eq!(read_pids(&pid_path)?, Vec::<String>::new());
+
+        write_pids(&pid_path, 123)?;
+        assert_eq!(
+            read_pids(&pid_path)?,
+            [process::id().to_string(), "123".into()]
+        );
+        Ok(())
+    }
+
+    #[test_case::test_case("kerek tunnel-ssh --foreground host", ProcessRole::Supervisor, true; "supervisor")]
+    #[test_case::test_case(
+        "/usr/bin/docker-kerek kerek tunnel-ssh --foreground host",
+        ProcessRole::Supervisor,
+        true;
+        "supervisor as plugin"
+    )]
+    #[test_case::test_case("grep tunnel-ssh", ProcessRole::Supervisor, false; "other as supervisor")]
+    #[test_case::test_case("ssh -N -o LocalForward host", ProcessRole::Ssh, true; "SSH")]
+    #[test_case::test_case("/usr/bin/ssh -N host", ProcessRole::Ssh, true; "SSH by path")]
+    #[test_case::test_case("sshd: user", ProcessRole::Ssh, false; "other as SSH")]
+    #[test_case::test_case("", ProcessRole::Ssh, false; "ended")]
+    fn is_role_handles(arguments: &str, role: ProcessRole, expected: bool) {
+        assert_eq!(is_role(arguments, role), expected)
+    }
+
+    #[test]
+    fn checks_process() {
+        let pid = process::id().to_string();
+        assert!(!is_process(&pid, ProcessRole::Supervisor));
+        assert!(!is_process("0", ProcessRole::Ssh));
+    }
+
+    fn serve_ping(mut stream: impl Read + Write) -> io::Result<String> {
+        let mut request = vec![0; 1024];
+        let count = stream.read(&mut request)?;
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK")?;
+        Ok(String::from_utf8_lossy(&request[..count]).into())
+    }
+}